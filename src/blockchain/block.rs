@@ -93,6 +93,26 @@ impl Block {
   }
 
 
+  /// Verifies the hash of the block and that it satisfies the proof of work complexity
+  pub(crate) fn check_proof_of_work(&self) -> Result<bool> {
+    let unmined_block: Self = Self {
+      timestamp: DateTime::default(),
+      signature: String::default(),
+      hash: Vec::default(),
+      ..self.clone()
+    };
+
+    let hash: Vec<u8> = unmined_block.hash()?;
+    Ok(hash == self.hash && hash.starts_with(&COMPLEXITY))
+  }
+
+
+  /// Full check of a block received from the network: signature, proof of work and the signature of its data
+  pub(crate) fn validate(&mut self) -> Result<bool> {
+    Ok(self.check(None)? && self.check_proof_of_work()? && self.data.check()?)
+  }
+
+
   pub(crate) fn create_first(data: Data, miner: PrivateKey)  -> Result<Self>{
     let mut block: Self = Self::new(
       0,
@@ -122,6 +142,13 @@ impl Block {
   }
 
 
+  /// A block without the proof of work and the signature, for the tests of code that only reads blocks
+  #[cfg(test)]
+  pub(crate) fn unmined(data: Data, miner: &PrivateKey) -> Result<Self> {
+    Ok(Self::new(0, Vec::default(), data, Utc::now(), Vec::default(), miner.public_key().to_openssh()?, String::default(), Vec::default()))
+  }
+
+
   pub(crate) fn create(data: Data, prev_block: Block, miner: PrivateKey) -> Result<Self> {
     let mut block: Self = Self::new(
       prev_block.id + 1,
@@ -161,11 +188,46 @@ impl Block {
   }
 
 
+  pub(crate) fn get_id(&self) -> u128 {
+    self.id
+  }
+
+
+  pub(crate) fn get_hash(&self) -> Vec<u8> {
+    self.hash.clone()
+  }
+
+
+  pub(crate) fn get_prev_block_hash(&self) -> Vec<u8> {
+    self.prev_block_hash.clone()
+  }
+
+
+  pub(crate) fn get_timestamp(&self) -> DateTime<Utc> {
+    self.timestamp
+  }
+
+
+  pub(crate) fn get_proof_of_work(&self) -> Vec<u8> {
+    self.proof_of_work.clone()
+  }
+
+
+  pub(crate) fn get_miner(&self) -> String {
+    self.miner.clone()
+  }
+
+
   pub(crate) fn get_data(&self) -> Vec<u8> {
     self.data.get_data()
   }
 
 
+  pub(crate) fn get_signed_data(&self) -> Data {
+    self.data.clone()
+  }
+
+
   pub(crate) fn get_data_type(&self) -> Type {
     self.data.get_type()
   }
@@ -0,0 +1,138 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  fs::{read_dir, File},
+  io::ErrorKind,
+  path::PathBuf,
+};
+
+use anyhow::{ensure, Result};
+
+use crate::{
+  blockchain::{block::Block, data::user::UserData, ledger::Ledger, user_record::UserQuery},
+  utils::data_path,
+};
+
+
+/// What `append` did with a block received from the network
+pub(crate) enum Appended {
+  Stored,
+  /// The block doesn't extend the local chain or is already stored
+  Skipped,
+  /// The block extends the local chain, but the ledger doesn't accept it
+  Rejected,
+}
+
+
+/// The blocks stored in `<DATA_DIR>/blockchain/` ordered by id
+pub(crate) fn blocks() -> Result<Vec<Block>> {
+  let mut blocks: Vec<Block> = Vec::new();
+  for block_path in read_dir(data_path("blockchain/")?)? {
+    blocks.push(Block::from_path(block_path?.path())?);
+  }
+  blocks.sort_by_key(Block::get_id);
+  Ok(blocks)
+}
+
+
+/// The number of blocks, taken from the file names without reading the blocks
+pub(crate) fn height() -> Result<u128> {
+  let mut height: u128 = 0;
+  for block_path in read_dir(data_path("blockchain/")?)? {
+    let id: Option<u128> = block_path?.path().file_stem().and_then(|stem| stem.to_str()?.parse().ok());
+    if let Some(id) = id {
+      height = height.max(id + 1);
+    }
+  }
+  Ok(height)
+}
+
+
+pub(crate) fn block_by_id(id: u128) -> Result<Option<Block>> {
+  let block_path: PathBuf = data_path("blockchain/")?.join(format!("{id}.json"));
+  match block_path.exists() {
+    true => Ok(Some(Block::from_path(block_path)?)),
+    false => Ok(None),
+  }
+}
+
+
+pub(crate) fn block_by_hash(hash: &[u8]) -> Result<Option<Block>> {
+  Ok(blocks()?.into_iter().find(|block: &Block| block.get_hash() == hash))
+}
+
+
+/// The block with the highest id
+pub(crate) fn tip() -> Result<Option<Block>> {
+  match height()? {
+    0 => Ok(None),
+    height => block_by_id(height - 1),
+  }
+}
+
+
+/// Fails when a block with the same id is already stored
+pub(crate) fn save(block: &Block) -> Result<()> {
+  let block_file: File = File::options().create_new(true).write(true).open(block.get_file_name()?)?;
+  serde_json::to_writer_pretty(block_file, block)?;
+  Ok(())
+}
+
+
+/// Stores a block received from the network if it extends the local chain and the ledger accepts it
+pub(crate) fn append(block: &Block) -> Result<Appended> {
+  let extends_tip: bool = match tip()? {
+    Some(tip) => block.get_id() == tip.get_id() + 1 && block.get_prev_block_hash() == tip.get_hash(),
+    None => block.get_id() == 0,
+  };
+  if !extends_tip {
+    return Ok(Appended::Skipped);
+  }
+  if !Ledger::load()?.accepts(block) {
+    return Ok(Appended::Rejected);
+  }
+
+  match save(block) {
+    Ok(_) => Ok(Appended::Stored),
+    // The client mined the block itself next to its embedded node
+    Err(error) if error.downcast_ref::<std::io::Error>().is_some_and(|error| error.kind() == ErrorKind::AlreadyExists) => Ok(Appended::Skipped),
+    Err(error) => Err(error),
+  }
+}
+
+
+/// Reads the whole chain without the DHT, users are identified by their registration and key rotations
+pub(crate) fn find_user(query: &UserQuery) -> Result<Option<UserData>> {
+  Ledger::load()?.find_user(query)
+}
+
+
+/// Checks the ids, the links, the signatures and the proof of work of every stored block, returns the height
+pub(crate) fn verify() -> Result<u128> {
+  let mut prev_block: Option<Block> = None;
+  for mut block in blocks()? {
+    let id: u128 = prev_block.as_ref().map_or(0, |prev_block: &Block| prev_block.get_id() + 1);
+    ensure!(block.get_id() == id, "Block {id} is missing");
+    ensure!(
+      block.check(prev_block.take())? && block.check_proof_of_work()? && block.get_signed_data().check()?,
+      "Block {id} is invalid",
+    );
+    prev_block = Some(block);
+  }
+  Ok(prev_block.map_or(0, |block: Block| block.get_id() + 1))
+}
@@ -16,13 +16,15 @@
 
 
 pub(crate) mod r#type;
+pub(crate) mod rotation;
+pub(crate) mod transfer;
 pub(crate) mod user;
 
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use ssh_key::{PrivateKey, HashAlg, LineEnding};
+use ssh_key::{PrivateKey, PublicKey, SshSig, HashAlg, LineEnding};
 
 use crate::blockchain::data::r#type::Type;
 
@@ -69,6 +71,25 @@ impl Data {
   }
 
 
+  /// Verifies that the data was signed by the owner of `public_key`
+  pub(crate) fn check(&self) -> Result<bool> {
+    let unsigned_data: Self = Self {
+      signature: String::new(),
+      ..self.clone()
+    };
+
+    let public_key: PublicKey = PublicKey::from_openssh(&self.public_key)?;
+    let signature: SshSig = SshSig::from_pem(&self.signature)?;
+
+    Ok(public_key.verify("system", &serde_json::to_vec(&unsigned_data)?, &signature).is_ok())
+  }
+
+
+  pub(crate) fn get_timestamp(&self) -> DateTime<Utc> {
+    self.timestamp
+  }
+
+
   pub(crate) fn get_data(&self) -> Vec<u8> {
     self.data.clone()
   }
@@ -77,4 +98,14 @@ impl Data {
   pub(crate) fn get_type(&self) -> Type {
     self.r#type.clone()
   }
+
+
+  pub(crate) fn get_public_key(&self) -> String {
+    self.public_key.clone()
+  }
+
+
+  pub(crate) fn get_miner_amount(&self) -> f64 {
+    self.miner_amount
+  }
 }
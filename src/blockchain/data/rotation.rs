@@ -0,0 +1,39 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use serde::{Serialize, Deserialize};
+
+
+/// Moves the identity of the user that signed the data to `new_public_key`
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct KeyRotationData {
+  new_public_key: String,
+}
+
+
+impl KeyRotationData {
+  pub(crate) fn new(new_public_key: String) -> Self {
+    Self {
+      new_public_key,
+    }
+  }
+
+
+  pub(crate) fn get_new_public_key(&self) -> String {
+    self.new_public_key.clone()
+  }
+}
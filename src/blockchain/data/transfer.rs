@@ -0,0 +1,46 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use serde::{Serialize, Deserialize};
+
+
+/// Money sent from the user that signed the data to the owner of `to`
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct TransferData {
+  to: String,
+  amount: f64,
+}
+
+
+impl TransferData {
+  pub(crate) fn new(to: String, amount: f64) -> Self {
+    Self {
+      to,
+      amount,
+    }
+  }
+
+
+  pub(crate) fn get_to(&self) -> String {
+    self.to.clone()
+  }
+
+
+  pub(crate) fn get_amount(&self) -> f64 {
+    self.amount
+  }
+}
@@ -22,4 +22,6 @@ use serde::{Serialize, Deserialize};
 pub(crate) enum Type {
   User,
   Transfer,
+  /// Signed by the old key of a user
+  KeyRotation,
 }
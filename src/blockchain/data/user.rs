@@ -21,7 +21,7 @@ use serde::{Serialize, Deserialize};
 use crate::user::User;
 
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct UserData {
   first_name: String,
   last_name: String,
@@ -110,4 +110,13 @@ impl UserData {
   pub(crate) fn get_public_key(&self) -> String {
     self.public_key.clone()
   }
+
+
+  /// The same user after a key rotation
+  pub(crate) fn with_public_key(self, public_key: String) -> Self {
+    Self {
+      public_key,
+      ..self
+    }
+  }
 }
@@ -0,0 +1,415 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ssh_key::{HashAlg, PublicKey};
+use tracing::debug;
+
+use crate::blockchain::{
+  block::Block,
+  chain,
+  data::{Data, r#type::Type, rotation::KeyRotationData, transfer::TransferData, user::UserData},
+  user_record::UserQuery,
+};
+
+
+/// The only source of money: the miner of every block gets it, the data has to ask for exactly this amount
+pub(crate) const BLOCK_REWARD: f64 = 10.0;
+
+
+/// Balances and users replayed from the local chain. The miner of a block is rewarded with `BLOCK_REWARD`,
+/// transfers move money between the signer of the data and the recipient and key rotations
+/// move the balance and the user to the new key. Retired keys can't do anything afterwards
+#[derive(Default)]
+pub(crate) struct Ledger {
+  balances: HashMap<String, f64>,
+  history: HashMap<String, Vec<Entry>>,
+  /// In the order of registration, with their current keys
+  users: Vec<UserData>,
+  /// Retired accounts and the keys that replaced them
+  rotations: HashMap<String, String>,
+}
+
+
+/// A change of the balance of one account
+#[derive(Clone, Serialize)]
+pub(crate) struct Entry {
+  block_id: u128,
+  timestamp: DateTime<Utc>,
+  kind: EntryKind,
+  amount: f64,
+  /// The other side of a transfer
+  counterparty: Option<String>,
+  /// The balance after the change
+  balance: f64,
+}
+
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EntryKind {
+  Reward,
+  Sent,
+  Received,
+  Rotation,
+}
+
+
+/// What an accepted block does to the ledger
+enum Change {
+  User(UserData),
+  Transfer {
+    recipient: String,
+    amount: f64,
+  },
+  KeyRotation {
+    new_public_key: String,
+  },
+}
+
+
+impl Ledger {
+  pub(crate) fn from_blocks(blocks: &[Block]) -> Self {
+    let mut ledger: Self = Self::default();
+    for block in blocks {
+      ledger.apply(block);
+    }
+    ledger
+  }
+
+
+  pub(crate) fn load() -> Result<Self> {
+    Ok(Self::from_blocks(&chain::blocks()?))
+  }
+
+
+  /// Whether the block is valid on top of the replayed blocks
+  pub(crate) fn accepts(&self, block: &Block) -> bool {
+    self.change(block).is_some()
+  }
+
+
+  /// Rejected blocks change nothing, one bad block must not make the whole chain unreadable
+  pub(crate) fn apply(&mut self, block: &Block) {
+    let Some(change) = self.change(block) else {
+      debug!(block_id = block.get_id(), "Skipped a block the ledger rejects");
+      return;
+    };
+
+    let signer: String = block.get_signed_data().get_public_key();
+    self.add(block, &block.get_miner(), EntryKind::Reward, BLOCK_REWARD, None);
+    match change {
+      Change::User(user) => self.users.push(user),
+      Change::Transfer { recipient, amount } => {
+        self.add(block, &signer, EntryKind::Sent, -amount, Some(recipient.clone()));
+        self.add(block, &recipient, EntryKind::Received, amount, Some(signer));
+      },
+      Change::KeyRotation { new_public_key } => self.rotate(block, &signer, new_public_key),
+    }
+  }
+
+
+  pub(crate) fn get_balance(&self, public_key: &PublicKey) -> f64 {
+    self.balances.get(&account(public_key)).copied().unwrap_or_default()
+  }
+
+
+  /// Oldest changes first
+  pub(crate) fn get_history(&self, public_key: &PublicKey) -> Vec<Entry> {
+    self.history.get(&account(public_key)).cloned().unwrap_or_default()
+  }
+
+
+  /// The first registered user matching the query, found by the current key only
+  pub(crate) fn find_user(&self, query: &UserQuery) -> Result<Option<UserData>> {
+    for user in &self.users {
+      if query.matches(user)? {
+        return Ok(Some(user.clone()));
+      }
+    }
+    Ok(None)
+  }
+
+
+  /// The key was replaced by a key rotation
+  pub(crate) fn is_retired(&self, public_key: &PublicKey) -> bool {
+    self.rotations.contains_key(&account(public_key))
+  }
+
+
+  /// The key has a history, a user or was retired, so a key rotation can't move an identity to it
+  pub(crate) fn is_known(&self, public_key: &PublicKey) -> bool {
+    let account: String = account(public_key);
+    self.history.contains_key(&account) || self.rotations.contains_key(&account) || self.users.iter().any(|user: &UserData| {
+      PublicKey::from_openssh(&user.get_public_key()).is_ok_and(|user_key: PublicKey| user_key.key_data() == public_key.key_data())
+    })
+  }
+
+
+  /// Checks the block against the replayed blocks, `None` when it is rejected: malformed keys or data,
+  /// retired keys, rewards other than `BLOCK_REWARD`, transfers of no money or more money than the signer has and
+  /// rotations to keys in use
+  fn change(&self, block: &Block) -> Option<Change> {
+    let data: Data = block.get_signed_data();
+    let miner: PublicKey = PublicKey::from_openssh(&block.get_miner()).ok()?;
+    let signer: PublicKey = PublicKey::from_openssh(&data.get_public_key()).ok()?;
+    if self.is_retired(&miner) || self.is_retired(&signer) {
+      return None;
+    }
+    // The signer picks the amount, anything else would let it create money
+    if data.get_miner_amount() != BLOCK_REWARD {
+      return None;
+    }
+
+    match data.get_type() {
+      Type::User => serde_json::from_slice(&data.get_data()).ok().map(Change::User),
+      Type::Transfer => {
+        let transfer: TransferData = serde_json::from_slice(&data.get_data()).ok()?;
+        PublicKey::from_openssh(&transfer.get_to()).ok()?;
+        // The miner of the block may spend its reward
+        let mut balance: f64 = self.get_balance(&signer);
+        if account(&miner) == account(&signer) {
+          balance += BLOCK_REWARD;
+        }
+
+        let amount: f64 = transfer.get_amount();
+        (amount.is_finite() && amount > 0.0 && amount <= balance).then(|| Change::Transfer {
+          // Money sent to a retired key reaches the user through the new key
+          recipient: self.current_key(transfer.get_to()),
+          amount,
+        })
+      },
+      Type::KeyRotation => {
+        let rotation: KeyRotationData = serde_json::from_slice(&data.get_data()).ok()?;
+        let new_key: PublicKey = PublicKey::from_openssh(&rotation.get_new_public_key()).ok()?;
+        (!self.is_known(&new_key)).then(|| Change::KeyRotation {
+          new_public_key: rotation.get_new_public_key(),
+        })
+      },
+    }
+  }
+
+
+  fn rotate(&mut self, block: &Block, old_public_key: &str, new_public_key: String) {
+    let Ok(old_key) = PublicKey::from_openssh(old_public_key) else {
+      return;
+    };
+    let balance: f64 = self.get_balance(&old_key);
+    self.add(block, old_public_key, EntryKind::Rotation, -balance, Some(new_public_key.clone()));
+    self.add(block, &new_public_key, EntryKind::Rotation, balance, Some(old_public_key.to_string()));
+
+    for user in &mut self.users {
+      if PublicKey::from_openssh(&user.get_public_key()).is_ok_and(|user_key: PublicKey| user_key.key_data() == old_key.key_data()) {
+        *user = user.clone().with_public_key(new_public_key.clone());
+      }
+    }
+    self.rotations.insert(account(&old_key), new_public_key);
+  }
+
+
+  /// Follows the key rotations of the account
+  pub(crate) fn current_key(&self, mut public_key: String) -> String {
+    while let Some(new_public_key) = PublicKey::from_openssh(&public_key).ok().and_then(|key: PublicKey| self.rotations.get(&account(&key))) {
+      public_key = new_public_key.clone();
+    }
+    public_key
+  }
+
+
+  /// Keys are checked by `change`, malformed ones are skipped
+  fn add(&mut self, block: &Block, public_key: &str, kind: EntryKind, amount: f64, counterparty: Option<String>) {
+    let Ok(public_key) = PublicKey::from_openssh(public_key) else {
+      return;
+    };
+    let account: String = account(&public_key);
+    let balance: &mut f64 = self.balances.entry(account.clone()).or_default();
+    *balance += amount;
+
+    self.history.entry(account).or_default().push(Entry {
+      block_id: block.get_id(),
+      timestamp: block.get_timestamp(),
+      kind,
+      amount,
+      counterparty,
+      balance: *balance,
+    });
+  }
+}
+
+
+/// Keys are compared without their comments
+fn account(public_key: &PublicKey) -> String {
+  public_key.fingerprint(HashAlg::Sha256).to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use ssh_key::{rand_core::OsRng, Algorithm, PrivateKey};
+
+  use super::*;
+
+
+  fn new_key() -> PrivateKey {
+    PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()
+  }
+
+
+  fn public_key(key: &PrivateKey) -> String {
+    key.public_key().to_openssh().unwrap()
+  }
+
+
+  /// The ledger checks neither the links nor the proof of work, so the signer "mines" without the work
+  fn mine<S: Serialize>(r#type: Type, data: S, key: &PrivateKey) -> Block {
+    mine_with_reward(r#type, data, BLOCK_REWARD, key)
+  }
+
+
+  fn mine_with_reward<S: Serialize>(r#type: Type, data: S, reward: f64, key: &PrivateKey) -> Block {
+    let data: Data = Data::create(r#type, data, reward, key.clone()).unwrap();
+    Block::unmined(data, key).unwrap()
+  }
+
+
+  fn register(key: &PrivateKey, user_name: &str) -> Block {
+    mine(Type::User, UserData::create_new("First", "Last", user_name, public_key(key)), key)
+  }
+
+
+  fn transfer(key: &PrivateKey, to: String, amount: f64) -> Block {
+    mine(Type::Transfer, TransferData::new(to, amount), key)
+  }
+
+
+  #[test]
+  fn rewards_the_miner() {
+    let alice: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[register(&alice, "alice")]);
+
+    assert_eq!(ledger.get_balance(alice.public_key()), BLOCK_REWARD);
+    assert_eq!(ledger.get_history(alice.public_key()).len(), 1);
+    let user: UserData = ledger.find_user(&UserQuery::UserName("alice".to_string())).unwrap().unwrap();
+    assert_eq!(user.get_public_key(), public_key(&alice));
+  }
+
+
+  #[test]
+  fn skips_inflated_rewards() {
+    let alice: PrivateKey = new_key();
+    let bob: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[
+      register(&alice, "alice"),
+      mine_with_reward(Type::Transfer, TransferData::new(public_key(&bob), 1000.0), 1000.0 + BLOCK_REWARD, &alice),
+    ]);
+
+    assert_eq!(ledger.get_balance(alice.public_key()), BLOCK_REWARD);
+    assert_eq!(ledger.get_balance(bob.public_key()), 0.0);
+    assert!(!ledger.accepts(&mine_with_reward(Type::User, UserData::create_new("First", "Last", "bob", public_key(&bob)), 0.0, &bob)));
+  }
+
+
+  #[test]
+  fn transfers_money() {
+    let alice: PrivateKey = new_key();
+    let bob: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[
+      register(&alice, "alice"),
+      transfer(&alice, public_key(&bob), 4.0),
+    ]);
+
+    assert_eq!(ledger.get_balance(alice.public_key()), 2.0 * BLOCK_REWARD - 4.0);
+    assert_eq!(ledger.get_balance(bob.public_key()), 4.0);
+  }
+
+
+  #[test]
+  fn skips_overspending() {
+    let alice: PrivateKey = new_key();
+    let bob: PrivateKey = new_key();
+    let mut ledger: Ledger = Ledger::from_blocks(&[register(&alice, "alice")]);
+    // The reward of the block itself can be spent
+    let block: Block = transfer(&alice, public_key(&bob), 2.0 * BLOCK_REWARD + 1.0);
+
+    assert!(!ledger.accepts(&block));
+    ledger.apply(&block);
+    assert_eq!(ledger.get_balance(alice.public_key()), BLOCK_REWARD);
+    assert_eq!(ledger.get_balance(bob.public_key()), 0.0);
+    assert!(ledger.accepts(&transfer(&alice, public_key(&bob), 2.0 * BLOCK_REWARD)));
+  }
+
+
+  #[test]
+  fn skips_amounts_that_are_not_positive() {
+    let alice: PrivateKey = new_key();
+    let bob: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[
+      register(&alice, "alice"),
+      transfer(&alice, public_key(&bob), -5.0),
+      transfer(&alice, public_key(&bob), 0.0),
+      transfer(&alice, public_key(&bob), f64::NAN),
+    ]);
+
+    assert_eq!(ledger.get_balance(alice.public_key()), BLOCK_REWARD);
+    assert_eq!(ledger.get_balance(bob.public_key()), 0.0);
+  }
+
+
+  #[test]
+  fn skips_malformed_blocks() {
+    let alice: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[
+      register(&alice, "alice"),
+      transfer(&alice, "not a key".to_string(), 1.0),
+      mine(Type::Transfer, "not a transfer", &alice),
+      mine(Type::KeyRotation, KeyRotationData::new("not a key".to_string()), &alice),
+    ]);
+
+    assert_eq!(ledger.get_balance(alice.public_key()), BLOCK_REWARD);
+    assert!(!ledger.is_retired(alice.public_key()));
+  }
+
+
+  #[test]
+  fn rotates_the_key() {
+    let alice: PrivateKey = new_key();
+    let new_alice: PrivateKey = new_key();
+    let bob: PrivateKey = new_key();
+    let ledger: Ledger = Ledger::from_blocks(&[
+      register(&alice, "alice"),
+      register(&bob, "bob"),
+      mine(Type::KeyRotation, KeyRotationData::new(public_key(&new_alice)), &alice),
+      // The retired key can't spend anymore
+      transfer(&alice, public_key(&bob), 1.0),
+      // Money sent to the retired key reaches the new key
+      transfer(&bob, public_key(&alice), 3.0),
+    ]);
+
+    assert!(ledger.is_retired(alice.public_key()));
+    assert!(ledger.is_known(new_alice.public_key()));
+    assert_eq!(ledger.get_balance(alice.public_key()), 0.0);
+    // The reward of the rotation block moves too
+    assert_eq!(ledger.get_balance(new_alice.public_key()), 2.0 * BLOCK_REWARD + 3.0);
+    assert_eq!(ledger.get_balance(bob.public_key()), 2.0 * BLOCK_REWARD - 3.0);
+    let user: UserData = ledger.find_user(&UserQuery::UserName("alice".to_string())).unwrap().unwrap();
+    assert_eq!(user.get_public_key(), public_key(&new_alice));
+  }
+}
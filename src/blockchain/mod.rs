@@ -16,27 +16,47 @@
 
 
 pub(crate) mod block;
+pub(crate) mod chain;
 pub(crate) mod data;
+pub(crate) mod ledger;
+pub(crate) mod user_record;
 
 
-use std::fs::{read_dir, File};
+use std::{
+  sync::{Mutex, MutexGuard, PoisonError},
+  time::Duration,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{ensure, Context, Result};
+use libp2p::PeerId;
+use serde::Serialize;
 use ssh_key::{PrivateKey, PublicKey};
+use tracing::{debug, info, warn};
 
 use crate::{
   blockchain::{
     block::Block,
-    data::{Data, user::UserData, r#type::Type},
+    data::{Data, rotation::KeyRotationData, transfer::TransferData, user::UserData, r#type::Type},
+    ledger::{Ledger, BLOCK_REWARD},
+    user_record::{UserQuery, UserRecord},
   },
+  config::Config,
   net::{Net, api::API},
   user::User,
-  utils::data_path,
 };
 
 
+/// How long commands wait for gossipsub peers before exiting
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long commands wait for the first connection before asking the network
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+
 pub(crate) struct Blockchain {
   net: API,
+  /// Held from the checks against the local chain until the block is stored, so concurrent RPC requests
+  /// can't both spend the same balance or mine on the same tip
+  mining_lock: Mutex<()>,
 }
 
 
@@ -44,52 +64,121 @@ impl Blockchain {
   fn new(net: API) -> Self {
     Self {
       net,
+      mining_lock: Mutex::default(),
     }
   }
 
 
-  pub(crate) fn from_key(key: &PrivateKey) -> Result<Self> {
-    let net: API = Net::from_key(&key)?;
+  pub(crate) fn from_key(key: &PrivateKey, config: &Config) -> Result<Self> {
+    let net: API = Net::start_client(key, &config.network)?;
     Ok(Self::new(net))
   }
 
 
   pub(crate) fn add_user(&self, user: &User) -> Result<()> {
-    let data: Data = Data::create(Type::User, UserData::from_user(user)?, 10.0, user.get_key())?;
+    let lock: MutexGuard<()> = self.lock_mining();
+    let block: Block = self.mine(&lock, Type::User, UserData::from_user(user)?, user.get_key())?;
+    self.publish_user(&block)?;
+    info!(user_name = %user.get_user_name(), "Registered the user");
+    Ok(())
+  }
+
+
+  /// Sends money to the owner of `to`, the sender must have enough money in the local chain
+  pub(crate) fn transfer(&self, key: PrivateKey, to: &PublicKey, amount: f64) -> Result<Block> {
+    ensure!(amount.is_finite() && amount > 0.0, "The amount must be a positive number");
+    let lock: MutexGuard<()> = self.lock_mining();
+    let ledger: Ledger = Ledger::load()?;
+    ensure!(!ledger.is_retired(key.public_key()), "The key was replaced by a key rotation");
+    let balance: f64 = ledger.get_balance(key.public_key());
+    ensure!(balance >= amount, "Not enough money, the balance is {balance}");
+
+    let block: Block = self.mine(&lock, Type::Transfer, TransferData::new(to.to_openssh()?, amount), key)?;
+    info!(block_id = block.get_id(), amount, "Transferred money");
+    Ok(block)
+  }
+
+
+  /// Moves the user, the balance included, from `key` to `new_key`, the old key can't be used afterwards
+  pub(crate) fn rotate_key(&self, key: PrivateKey, new_key: &PublicKey) -> Result<Block> {
+    let lock: MutexGuard<()> = self.lock_mining();
+    let ledger: Ledger = Ledger::load()?;
+    ensure!(!ledger.is_retired(key.public_key()), "The key was already replaced by a key rotation");
+    ensure!(!ledger.is_known(new_key), "The new key is already used in the chain");
+
+    let block: Block = self.mine(&lock, Type::KeyRotation, KeyRotationData::new(new_key.to_openssh()?), key)?;
+    info!(block_id = block.get_id(), "Rotated the key");
+    Ok(block)
+  }
+
+
+  pub(crate) fn list_peers(&self) -> Result<Vec<PeerId>> {
+    self.net.list_peers()
+  }
+
+
+  /// Lookups right after the start would only see the local records
+  pub(crate) fn wait_for_peers(&self) -> Result<()> {
+    if !self.net.wait_for_peers(CONNECT_TIMEOUT)? {
+      warn!("No peers connected, only the local chain is available");
+    }
+    Ok(())
+  }
+
+
+  /// Waits until the mined blocks reach the network, `false` when no peer took them in time
+  pub(crate) fn flush(&self) -> Result<bool> {
+    self.net.flush(FLUSH_TIMEOUT)
+  }
+
+
+  /// The lock only guards the local chain, a panic while holding it leaves nothing half done
+  fn lock_mining(&self) -> MutexGuard<'_, ()> {
+    self.mining_lock.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+
+  /// Announces the data, mines it into a block on top of the local chain, stores the block and sends it.
+  /// The block is stored first, so a block that loses the race for its id is never sent
+  fn mine<S: Serialize>(&self, _lock: &MutexGuard<()>, r#type: Type, data: S, key: PrivateKey) -> Result<Block> {
+    let data: Data = Data::create(r#type, data, BLOCK_REWARD, key.clone())?;
     self.net.send_block_data(&data)?;
-    let block: Block = match read_dir(data_path("blockchain/")?)?.last() {
-      Some(block_path) => Block::create(data, Block::from_path(block_path?.path())?, user.get_key())?,
-      None => Block::create_first(data, user.get_key())?,
+    let block: Block = match chain::tip()? {
+      Some(prev_block) => Block::create(data, prev_block, key)?,
+      None => Block::create_first(data, key)?,
     };
+    chain::save(&block)?;
     self.net.send_block(&block)?;
-    let block_file: File = File::options().create_new(true).write(true).open(block.get_file_name()?)?;
-    serde_json::to_writer_pretty(block_file, &block)?;
-    Ok(())
+    Ok(block)
   }
 
 
-  pub(crate) fn get_user(&self, public_key: &PublicKey) -> Result<UserData> {
-    let public_key: String = public_key.to_openssh()?;
-    // let user_data: UserData = UserData::default();
-    let mut prev_block: Option<Block> = None;
-    for block_path in read_dir(data_path("blockchain/")?)? {
-      let mut block: Block = Block::from_path(block_path?.path())?;
-      if !block.check(prev_block)? {
-        todo!("Add blockchain repairing");
+  fn publish_user(&self, block: &Block) -> Result<()> {
+    if let Some(record) = UserRecord::from_block(block) {
+      for (key, value) in record.entries()? {
+        self.net.put_record(key, value)?;
       }
-      prev_block = Some(block.clone());
-
-      match block.get_data_type() {
-        Type::User => {
-          let user_data: UserData = serde_json::from_slice::<UserData>(&block.get_data())?;
-          if user_data.get_public_key() == public_key {
-            return Ok(user_data);
-          }
-        },
-        Type::Transfer => (),
+    }
+    Ok(())
+  }
+
+
+  /// Looks the user up in the DHT, the local chain is searched when there is no valid record.
+  /// Anyone can publish a record, so a record counts only when the local chain stores the very block it was taken from
+  pub(crate) fn find_user(&self, query: &UserQuery) -> Result<UserData> {
+    if let Some(value) = self.net.get_record(query.key())? {
+      let record: UserRecord = serde_json::from_slice(&value)?;
+      if record.check()? && record.check_chain()? {
+        let user_data: UserData = record.get_user_data()?;
+        if query.matches(&user_data)? {
+          debug!(user_name = %user_data.get_user_name(), "Found the user in the DHT");
+          return Ok(user_data);
+        }
       }
+      debug!("The local chain doesn't confirm the user record of the DHT");
     }
 
-    bail!("User data not found in blockchain");
+    debug!("Searching the local chain for the user");
+    chain::find_user(query)?.context("User data not found in blockchain")
   }
 }
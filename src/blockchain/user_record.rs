@@ -0,0 +1,133 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use ssh_key::{HashAlg, PublicKey};
+
+use crate::{
+  blockchain::{
+    block::Block,
+    data::{Data, user::UserData, r#type::Type},
+  },
+  utils::data_path,
+};
+
+
+/// A user registration stored in the DHT under the user name and under the public key.
+/// The data keeps the signature of the user, so nodes can't forge records, only replay them
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct UserRecord {
+  data: Data,
+  block_id: u128,
+  block_hash: Vec<u8>,
+}
+
+
+pub(crate) enum UserQuery {
+  UserName(String),
+  PublicKey(PublicKey),
+}
+
+
+impl UserRecord {
+  fn new(data: Data, block_id: u128, block_hash: Vec<u8>) -> Self {
+    Self {
+      data,
+      block_id,
+      block_hash,
+    }
+  }
+
+
+  /// The record of the user registered by the block, `None` for other blocks
+  pub(crate) fn from_block(block: &Block) -> Option<Self> {
+    match block.get_data_type() {
+      Type::User => Some(Self::new(block.get_signed_data(), block.get_id(), block.get_hash())),
+      Type::Transfer | Type::KeyRotation => None,
+    }
+  }
+
+
+  pub(crate) fn get_user_data(&self) -> Result<UserData> {
+    Ok(serde_json::from_slice(&self.data.get_data())?)
+  }
+
+
+  /// Verifies the signature and that the user data belongs to the key that signed it
+  pub(crate) fn check(&self) -> Result<bool> {
+    if !matches!(self.data.get_type(), Type::User) || !self.data.check()? {
+      return Ok(false);
+    }
+
+    let signer: PublicKey = PublicKey::from_openssh(&self.data.get_public_key())?;
+    let user_key: PublicKey = PublicKey::from_openssh(&self.get_user_data()?.get_public_key())?;
+    Ok(signer.key_data() == user_key.key_data())
+  }
+
+
+  /// Verifies the record against the local chain, records of blocks that weren't received yet can't be trusted
+  pub(crate) fn check_chain(&self) -> Result<bool> {
+    let block_path: PathBuf = data_path("blockchain/")?.join(format!("{}.json", self.block_id));
+    if !block_path.exists() {
+      return Ok(false);
+    }
+
+    let block: Block = Block::from_path(block_path)?;
+    Ok(block.get_hash() == self.block_hash && serde_json::to_vec(&block.get_signed_data())? == serde_json::to_vec(&self.data)?)
+  }
+
+
+  /// The DHT keys and the serialized record stored under each of them
+  pub(crate) fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let user_data: UserData = self.get_user_data()?;
+    let value: Vec<u8> = serde_json::to_vec(self)?;
+    Ok(vec![
+      (UserQuery::UserName(user_data.get_user_name()).key(), value.clone()),
+      (UserQuery::PublicKey(PublicKey::from_openssh(&user_data.get_public_key())?).key(), value),
+    ])
+  }
+}
+
+
+impl UserQuery {
+  /// A public key in the OpenSSH format, anything else is taken as a user name
+  pub(crate) fn parse(query: &str) -> Self {
+    match PublicKey::from_openssh(query) {
+      Ok(public_key) => Self::PublicKey(public_key),
+      Err(_) => Self::UserName(query.to_string()),
+    }
+  }
+
+
+  pub(crate) fn key(&self) -> Vec<u8> {
+    match self {
+      Self::UserName(user_name) => format!("/system/user/name/{user_name}").into_bytes(),
+      Self::PublicKey(public_key) => format!("/system/user/key/{}", public_key.fingerprint(HashAlg::Sha256)).into_bytes(),
+    }
+  }
+
+
+  pub(crate) fn matches(&self, user_data: &UserData) -> Result<bool> {
+    Ok(match self {
+      Self::UserName(user_name) => user_data.get_user_name() == *user_name,
+      Self::PublicKey(public_key) => PublicKey::from_openssh(&user_data.get_public_key())?.key_data() == public_key.key_data(),
+    })
+  }
+}
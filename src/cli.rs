@@ -0,0 +1,345 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Args, ArgAction, Parser, Subcommand};
+use libp2p::Multiaddr;
+
+use crate::net::{ban_list::BanTarget, config::TimingProfile, role::Role};
+
+
+#[derive(Parser)]
+#[command(version, about)]
+pub(crate) struct Cli {
+  /// Directory where keys, the blockchain and the server list are stored
+  #[arg(long, global = true, env = "SYSTEM_DATA_DIR")]
+  pub(crate) data_dir: Option<PathBuf>,
+
+  /// Configuration file [default: <DATA_DIR>/config.json]
+  #[arg(long, global = true, env = "SYSTEM_CONFIG")]
+  pub(crate) config: Option<PathBuf>,
+
+  /// Log more: -v debug of the program, -vv also debug of libp2p, -vvv trace of everything
+  #[arg(short, long, global = true, action = ArgAction::Count)]
+  pub(crate) verbose: u8,
+
+  /// Account of the data directory to use, needed when there are several
+  #[arg(long, global = true, env = "SYSTEM_ACCOUNT")]
+  pub(crate) account: Option<String>,
+
+  /// File holding the password of the local key, `SYSTEM_PASSWORD` takes precedence
+  #[arg(long, global = true, env = "SYSTEM_PASSWORD_FILE")]
+  pub(crate) password_file: Option<PathBuf>,
+
+  /// Command printing the password of the local key, e.g. a secret manager or a password agent
+  #[arg(long, global = true, env = "SYSTEM_PASSWORD_COMMAND")]
+  pub(crate) password_command: Option<String>,
+
+  #[command(subcommand)]
+  pub(crate) command: Option<Command>,
+}
+
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+  /// Run a headless bootstrap/relay node without the interactive menu
+  Node(NodeArgs),
+  /// Run the interactive client (default)
+  Client(ClientArgs),
+  /// Generate a persistent identity key for a node
+  Keygen(KeygenArgs),
+  /// Manage banned peers, running nodes pick up the changes within 30 seconds
+  Peers(PeersArgs),
+  /// Serve a JSON-RPC API for scripts and other front-ends on behalf of the local user
+  Rpc(RpcArgs),
+  /// Print the balance of the local user
+  Balance,
+  /// Transfer money to a user and wait until the block reaches the network
+  Transfer(TransferArgs),
+  /// Look up registered users
+  User(UserArgs),
+  /// Inspect the local chain
+  Chain(ChainArgs),
+  /// Change the password of the local key, the new one is typed in twice
+  Password,
+  /// List the accounts of the data directory with their public keys
+  Accounts,
+  /// Copy the encrypted key of the local account to a file, or write a backup archive
+  Export(ExportArgs),
+  /// Add an account from an exported key or a backup archive after checking it
+  Import(ImportArgs),
+}
+
+
+impl Default for Command {
+  fn default() -> Self {
+    Self::Client(ClientArgs::default())
+  }
+}
+
+
+#[derive(Args, Clone, Default)]
+pub(crate) struct NetArgs {
+  /// Network to join, nodes of different chains do not talk to each other [default: mainnet]
+  #[arg(long = "chain", value_name = "CHAIN_ID")]
+  pub(crate) chain_id: Option<String>,
+
+  /// Address to listen on, may be repeated (overrides the transport and port options below)
+  #[arg(long = "listen", value_name = "MULTIADDR")]
+  pub(crate) listen_addrs: Vec<Multiaddr>,
+
+  /// Address announced to other peers, may be repeated
+  #[arg(long = "external", value_name = "MULTIADDR")]
+  pub(crate) external_addrs: Vec<Multiaddr>,
+
+  /// TCP port to listen on for IPv4 and IPv6 [default: random]
+  #[arg(long)]
+  pub(crate) tcp_port: Option<u16>,
+
+  /// UDP port to listen on for QUIC over IPv4 and IPv6 [default: random]
+  #[arg(long)]
+  pub(crate) quic_port: Option<u16>,
+
+  /// Peer to join the network through, ending with `/p2p/<PEER_ID>`, may be repeated
+  #[arg(long = "bootstrap", value_name = "MULTIADDR")]
+  pub(crate) bootstrap_peers: Vec<Multiaddr>,
+
+  /// Host publishing `dnsaddr` records of bootstrap peers, may be repeated
+  #[arg(long = "dns-seed", value_name = "HOST")]
+  pub(crate) dns_seeds: Vec<String>,
+
+  /// Discover peers on the local network with mDNS
+  #[arg(long)]
+  pub(crate) mdns: bool,
+
+  /// Timing profile of the network behaviours [default: production]
+  #[arg(long, value_name = "PROFILE")]
+  pub(crate) timing: Option<TimingProfile>,
+
+  /// Serve Prometheus metrics of the node on `http://<ADDR>/metrics`
+  #[arg(long, value_name = "ADDR")]
+  pub(crate) metrics: Option<SocketAddr>,
+
+  /// Serve the block explorer of a full node on `http://<ADDR>/blocks`
+  #[arg(long, value_name = "ADDR")]
+  pub(crate) explorer: Option<SocketAddr>,
+
+  /// Do not listen on TCP
+  #[arg(long)]
+  pub(crate) no_tcp: bool,
+
+  /// Do not listen on QUIC
+  #[arg(long)]
+  pub(crate) no_quic: bool,
+
+  /// Do not listen on IPv4
+  #[arg(long)]
+  pub(crate) no_ipv4: bool,
+
+  /// Do not listen on IPv6
+  #[arg(long)]
+  pub(crate) no_ipv6: bool,
+}
+
+
+#[derive(Args)]
+pub(crate) struct NodeArgs {
+  /// Identity key of the node, generated with `keygen` [default: <DATA_DIR>/node_key.pem]
+  #[arg(long)]
+  pub(crate) key: Option<PathBuf>,
+
+  /// What the node does in the network
+  #[arg(long, value_enum, default_value = "full")]
+  pub(crate) role: Role,
+
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args, Default)]
+pub(crate) struct ClientArgs {
+  /// Also run a node in the background of the interactive client
+  #[arg(long)]
+  pub(crate) with_node: bool,
+
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args)]
+pub(crate) struct RpcArgs {
+  /// TCP address of the API, only loopback addresses are allowed (`--listen` is the address of the node)
+  #[arg(long, default_value = "127.0.0.1:7545")]
+  pub(crate) rpc_listen: SocketAddr,
+
+  /// Serve the API on a Unix socket instead of TCP
+  #[arg(long, conflicts_with = "rpc_listen")]
+  pub(crate) socket: Option<PathBuf>,
+
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args)]
+pub(crate) struct TransferArgs {
+  /// User name or public key of the recipient
+  #[arg(long)]
+  pub(crate) to: String,
+
+  #[arg(long)]
+  pub(crate) amount: f64,
+
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args)]
+pub(crate) struct UserArgs {
+  #[command(subcommand)]
+  pub(crate) command: UserCommand,
+}
+
+
+#[derive(Subcommand)]
+pub(crate) enum UserCommand {
+  /// Find a user by the user name or the public key
+  Show(UserShowArgs),
+  /// Move the local user and the balance to a new key, the old key is retired in the chain
+  RotateKey(UserRotateKeyArgs),
+}
+
+
+#[derive(Args)]
+pub(crate) struct UserShowArgs {
+  /// User name or public key
+  pub(crate) query: String,
+
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args)]
+pub(crate) struct UserRotateKeyArgs {
+  #[command(flatten)]
+  pub(crate) net: NetArgs,
+}
+
+
+#[derive(Args)]
+pub(crate) struct ChainArgs {
+  #[command(subcommand)]
+  pub(crate) command: ChainCommand,
+}
+
+
+#[derive(Subcommand)]
+pub(crate) enum ChainCommand {
+  /// Check the links, signatures and proof of work of every block, fails on the first invalid one
+  Verify,
+}
+
+
+#[derive(Args)]
+pub(crate) struct ExportArgs {
+  /// Where to write the key or the archive, an existing file is never overwritten
+  pub(crate) output: PathBuf,
+
+  /// Write an xz compressed tar archive with the key, the account metadata, checksums and the config
+  #[arg(long)]
+  pub(crate) backup: bool,
+}
+
+
+#[derive(Args)]
+pub(crate) struct ImportArgs {
+  /// An exported key or a backup archive, the account is named after the file or the backup
+  /// unless `--account` is given
+  pub(crate) input: PathBuf,
+}
+
+
+#[derive(Args)]
+pub(crate) struct KeygenArgs {
+  /// Where to write the key [default: <DATA_DIR>/node_key.pem]
+  #[arg(long)]
+  pub(crate) output: Option<PathBuf>,
+
+  /// Overwrite the key if it already exists
+  #[arg(long)]
+  pub(crate) force: bool,
+}
+
+
+#[derive(Args)]
+pub(crate) struct PeersArgs {
+  #[command(subcommand)]
+  pub(crate) command: PeersCommand,
+}
+
+
+#[derive(Subcommand)]
+pub(crate) enum PeersCommand {
+  /// List the banned peers and addresses and the peers known from previous runs
+  List,
+  /// Ban a peer ID or an IP address
+  Ban(BanArgs),
+  /// Lift the ban of a peer ID or an IP address
+  Unban(UnbanArgs),
+}
+
+
+#[derive(Args)]
+pub(crate) struct BanArgs {
+  /// Peer ID or IP address
+  pub(crate) target: BanTarget,
+
+  /// Duration of the ban in seconds [default: forever]
+  #[arg(long)]
+  pub(crate) duration: Option<i64>,
+
+  /// Why the peer is banned
+  #[arg(long, default_value = "Banned by the administrator")]
+  pub(crate) reason: String,
+}
+
+
+#[derive(Args)]
+pub(crate) struct UnbanArgs {
+  /// Peer ID or IP address
+  pub(crate) target: BanTarget,
+}
+
+
+#[cfg(test)]
+mod tests {
+  use clap::CommandFactory;
+
+  use super::*;
+
+
+  /// Clap only finds conflicting options when they are parsed, this catches them for every subcommand
+  #[test]
+  fn arguments_are_consistent() {
+    Cli::command().debug_assert();
+  }
+}
@@ -0,0 +1,144 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  env::vars,
+  fs::File,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::{net::config::NetConfig, utils::data_dir};
+
+
+const ENV_PREFIX: &str = "SYSTEM_";
+const ENV_SEPARATOR: &str = "__";
+
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+  pub(crate) data_dir: Option<PathBuf>,
+  pub(crate) network: NetConfig,
+  pub(crate) ui: UiConfig,
+  pub(crate) log: LogConfig,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct UiConfig {
+  pub(crate) prompt: String,
+  pub(crate) show_descriptions: bool,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct LogConfig {
+  /// Filter directives, e.g. `info` or `warn,system::net=debug`
+  pub(crate) level: String,
+  /// Also write JSON lines to `<DATA_DIR>/system.log`
+  pub(crate) json_file: bool,
+}
+
+
+impl Default for UiConfig {
+  fn default() -> Self {
+    Self {
+      prompt: String::from("~$ "),
+      show_descriptions: true,
+    }
+  }
+}
+
+
+impl Default for LogConfig {
+  fn default() -> Self {
+    Self {
+      level: String::from("info"),
+      json_file: false,
+    }
+  }
+}
+
+
+impl Config {
+  /// Loads the config from `path` or `<DATA_DIR>/config.json` and applies the `SYSTEM_*` environment overrides.
+  /// Only the default file may be missing
+  pub(crate) fn load(path: Option<PathBuf>) -> Result<Self> {
+    let (path, optional): (PathBuf, bool) = match path {
+      Some(path) => (path, false),
+      None => (data_dir()?.join("config.json"), true),
+    };
+
+    let mut value: Value = match path.exists() {
+      true => Self::read_value(&path)?,
+      false if optional => Value::Object(Map::new()),
+      false => bail!("The config {} was not found", path.display()),
+    };
+
+    for (name, env_value) in vars() {
+      if let Some(name) = name.strip_prefix(ENV_PREFIX) {
+        let keys: Vec<String> = name.split(ENV_SEPARATOR).map(str::to_lowercase).collect();
+        Self::set_value(&mut value, &keys, &env_value);
+      }
+    }
+
+    serde_json::from_value(value).with_context(|| format!("Invalid configuration in {}", path.display()))
+  }
+
+
+  fn read_value<P: AsRef<Path>>(path: P) -> Result<Value> {
+    let file: File = File::options().truncate(false).read(true).open(path)?;
+    Ok(serde_json::from_reader(file)?)
+  }
+
+
+  /// Only overrides keys that already exist in the config structure, so unrelated `SYSTEM_*` variables are ignored
+  fn set_value(value: &mut Value, keys: &[String], env_value: &str) {
+    let defaults: Value = match serde_json::to_value(Self::default()) {
+      Ok(defaults) => defaults,
+      Err(_) => return,
+    };
+
+    let mut known: &Value = &defaults;
+    for key in keys {
+      match known.get(key) {
+        Some(next) => known = next,
+        None => return,
+      }
+    }
+
+    let mut target: &mut Value = value;
+    for key in &keys[..keys.len() - 1] {
+      if !target.get(key).is_some_and(Value::is_object) {
+        target[key] = Value::Object(Map::new());
+      }
+      target = &mut target[key];
+    }
+
+    let env_value: Value = match known {
+      Value::String(_) => Value::String(env_value.to_string()),
+      _ => serde_json::from_str(env_value).unwrap_or_else(|_| Value::String(env_value.to_string())),
+    };
+    target[&keys[keys.len() - 1]] = env_value;
+  }
+}
@@ -0,0 +1,141 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use axum::{
+  extract::{
+    ws::{Message, WebSocket, WebSocketUpgrade},
+    Query,
+    State,
+  },
+  response::Response,
+};
+use serde::{Deserialize, Serialize};
+use ssh_key::PublicKey;
+use tokio::sync::broadcast::{error::RecvError, Receiver, Sender};
+use tracing::debug;
+
+use crate::{
+  blockchain::{block::Block, data::transfer::TransferData},
+  explorer::{view::{BlockSummary, DataView}, Error},
+  utils::to_hex,
+};
+
+
+/// What the node saw in the network, streamed to WebSocket subscribers as JSON
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub(crate) enum Event {
+  /// A block appended to the local chain
+  Block(BlockSummary),
+  /// Block data announced to be mined
+  Mempool(DataView),
+  /// A valid block competing with the stored block of the same id
+  Reorg {
+    id: u128,
+    stored_hash: String,
+    received_hash: String,
+  },
+  /// A transfer in a block appended to the local chain, only sent to the recipient
+  Transfer {
+    block_id: u128,
+    from: String,
+    to: String,
+    /// The key credited with the money, the new key when `to` was replaced by a key rotation
+    recipient: String,
+    amount: f64,
+  },
+}
+
+
+#[derive(Deserialize)]
+pub(crate) struct Subscription {
+  /// Also stream the transfers to this key
+  public_key: Option<String>,
+}
+
+
+impl Event {
+  pub(crate) fn reorg(stored: &Block, received: &Block) -> Self {
+    Self::Reorg {
+      id: stored.get_id(),
+      stored_hash: to_hex(&stored.get_hash()),
+      received_hash: to_hex(&received.get_hash()),
+    }
+  }
+
+
+  pub(crate) fn transfer(block: &Block, transfer: &TransferData, recipient: String) -> Self {
+    Self::Transfer {
+      block_id: block.get_id(),
+      from: block.get_signed_data().get_public_key(),
+      to: transfer.get_to(),
+      recipient,
+      amount: transfer.get_amount(),
+    }
+  }
+
+
+  fn is_for(&self, public_key: Option<&PublicKey>) -> bool {
+    match self {
+      Self::Transfer { recipient, .. } => public_key.is_some_and(|public_key: &PublicKey| {
+        PublicKey::from_openssh(recipient).is_ok_and(|recipient: PublicKey| recipient.key_data() == public_key.key_data())
+      }),
+      _ => true,
+    }
+  }
+}
+
+
+pub(crate) async fn subscribe(
+  websocket: WebSocketUpgrade,
+  Query(subscription): Query<Subscription>,
+  State(events): State<Sender<Event>>,
+) -> Result<Response, Error> {
+  let public_key: Option<PublicKey> = match subscription.public_key {
+    Some(public_key) => Some(PublicKey::from_openssh(&public_key).map_err(Error::bad_request)?),
+    None => None,
+  };
+  let receiver: Receiver<Event> = events.subscribe();
+  Ok(websocket.on_upgrade(move |socket: WebSocket| stream_events(socket, receiver, public_key)))
+}
+
+
+/// Runs until the subscriber disconnects, subscribers that fall behind miss the oldest events
+async fn stream_events(mut socket: WebSocket, mut receiver: Receiver<Event>, public_key: Option<PublicKey>) {
+  loop {
+    tokio::select! {
+      event = receiver.recv() => match event {
+        Ok(event) if event.is_for(public_key.as_ref()) => {
+          let Ok(text) = serde_json::to_string(&event) else {
+            continue;
+          };
+          if socket.send(Message::Text(text)).await.is_err() {
+            break;
+          }
+        },
+        Ok(_) => (),
+        Err(RecvError::Lagged(skipped)) => debug!(skipped, "A WebSocket subscriber missed events"),
+        Err(RecvError::Closed) => break,
+      },
+
+      message = socket.recv() => match message {
+        Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+        Some(Ok(_)) => (),
+      },
+    }
+  }
+}
@@ -0,0 +1,255 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+pub(crate) mod events;
+mod view;
+
+
+use std::net::{SocketAddr, TcpListener};
+
+use anyhow::{Context, Result};
+use axum::{
+  extract::{Path, Query},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  routing::get,
+  Json,
+  Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use ssh_key::PublicKey;
+use tokio::{
+  sync::broadcast::{self, Sender},
+  task::{self, JoinHandle},
+};
+use tracing::{info, warn};
+
+use crate::{
+  blockchain::{
+    block::Block,
+    chain,
+    data::{Data, r#type::Type, transfer::TransferData, user::UserData},
+    ledger::Ledger,
+    user_record::UserQuery,
+  },
+  explorer::{
+    events::{subscribe, Event},
+    view::{BlockSummary, BlockView, DataView, HistoryView, UserView},
+  },
+  utils::from_hex,
+};
+
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+/// Events kept for subscribers that are slower than the network
+const EVENTS_CAPACITY: usize = 256;
+
+
+/// Read-only HTTP API over the chain store of a full node with a WebSocket stream of network events
+pub(crate) struct Explorer {
+  server: JoinHandle<()>,
+  events: Sender<Event>,
+}
+
+
+#[derive(Deserialize)]
+struct BlocksQuery {
+  /// Only blocks with a lower id, the newest blocks by default
+  before: Option<u128>,
+  limit: Option<usize>,
+}
+
+
+#[derive(Deserialize)]
+struct UserSearch {
+  /// User name or public key
+  query: String,
+}
+
+
+pub(crate) struct Error {
+  status: StatusCode,
+  message: String,
+}
+
+
+impl Explorer {
+  pub(crate) fn serve(addr: SocketAddr) -> Result<Self> {
+    let listener: TcpListener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let listener: tokio::net::TcpListener = tokio::net::TcpListener::from_std(listener)?;
+    let (events, _): (Sender<Event>, _) = broadcast::channel(EVENTS_CAPACITY);
+    let router: Router = Router::new()
+    .route("/blocks", get(list_blocks))
+    .route("/blocks/:block", get(get_block))
+    .route("/users", get(find_user))
+    .route("/users/history", get(user_history))
+    .route("/events", get(subscribe))
+    .with_state(events.clone());
+
+    info!(%addr, "Serving the block explorer");
+    let server: JoinHandle<()> = task::spawn(async move {
+      if let Err(error) = axum::serve(listener, router).await {
+        warn!(%error, "The block explorer stopped");
+      }
+    });
+    Ok(Self {
+      server,
+      events,
+    })
+  }
+
+
+  pub(crate) fn block_stored(&self, block: &Block) {
+    self.publish(Event::Block(BlockSummary::from_block(block)));
+    if let Type::Transfer = block.get_data_type() {
+      if let Ok(transfer) = serde_json::from_slice::<TransferData>(&block.get_data()) {
+        // The ledger sends money addressed to a retired key to the key that replaced it
+        let recipient: String = match Ledger::load() {
+          Ok(ledger) => ledger.current_key(transfer.get_to()),
+          Err(_) => transfer.get_to(),
+        };
+        self.publish(Event::transfer(block, &transfer, recipient));
+      }
+    }
+  }
+
+
+  pub(crate) fn block_competing(&self, stored: &Block, received: &Block) {
+    self.publish(Event::reorg(stored, received));
+  }
+
+
+  pub(crate) fn data_received(&self, data: &Data) {
+    if let Ok(data) = DataView::from_data(data) {
+      self.publish(Event::Mempool(data));
+    }
+  }
+
+
+  /// Events without subscribers are dropped
+  fn publish(&self, event: Event) {
+    self.events.send(event).ok();
+  }
+}
+
+
+impl Drop for Explorer {
+  /// A restarted node binds the address again
+  fn drop(&mut self) {
+    self.server.abort();
+  }
+}
+
+
+impl Error {
+  fn not_found<M: Into<String>>(message: M) -> Self {
+    Self {
+      status: StatusCode::NOT_FOUND,
+      message: message.into(),
+    }
+  }
+
+
+  pub(crate) fn bad_request<M: ToString>(message: M) -> Self {
+    Self {
+      status: StatusCode::BAD_REQUEST,
+      message: message.to_string(),
+    }
+  }
+}
+
+
+impl From<anyhow::Error> for Error {
+  fn from(error: anyhow::Error) -> Self {
+    Self {
+      status: StatusCode::INTERNAL_SERVER_ERROR,
+      message: error.to_string(),
+    }
+  }
+}
+
+
+impl IntoResponse for Error {
+  fn into_response(self) -> Response {
+    (self.status, Json(json!({ "error": self.message }))).into_response()
+  }
+}
+
+
+/// Newest blocks first
+async fn list_blocks(Query(query): Query<BlocksQuery>) -> Result<Json<Vec<BlockSummary>>, Error> {
+  blocking(move || {
+    let height: u128 = chain::height()?;
+    let before: u128 = query.before.unwrap_or(height).min(height);
+    let limit: usize = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let mut blocks: Vec<BlockSummary> = Vec::new();
+    for id in (0..before).rev().take(limit) {
+      if let Some(block) = chain::block_by_id(id)? {
+        blocks.push(BlockSummary::from_block(&block));
+      }
+    }
+    Ok(blocks)
+  }).await
+}
+
+
+/// The block by its id or its hexadecimal hash
+async fn get_block(Path(block): Path<String>) -> Result<Json<BlockView>, Error> {
+  blocking(move || {
+    let found: Option<Block> = match block.parse::<u128>() {
+      Ok(id) => chain::block_by_id(id)?,
+      Err(_) => chain::block_by_hash(&from_hex(&block).map_err(Error::bad_request)?)?,
+    };
+    let found: Block = found.ok_or_else(|| Error::not_found(format!("Block {block} not found")))?;
+    Ok(BlockView::from_block(&found)?)
+  }).await
+}
+
+
+async fn find_user(Query(search): Query<UserSearch>) -> Result<Json<UserView>, Error> {
+  blocking(move || {
+    let user: UserData = chain_user(&search.query)?;
+    let balance: f64 = Ledger::load()?.get_balance(&PublicKey::from_openssh(&user.get_public_key()).map_err(anyhow::Error::from)?);
+    Ok(UserView::new(user, balance))
+  }).await
+}
+
+
+async fn user_history(Query(search): Query<UserSearch>) -> Result<Json<HistoryView>, Error> {
+  blocking(move || {
+    let user: UserData = chain_user(&search.query)?;
+    let public_key: PublicKey = PublicKey::from_openssh(&user.get_public_key()).map_err(anyhow::Error::from)?;
+    let ledger: Ledger = Ledger::load()?;
+    Ok(HistoryView::new(user.get_public_key(), ledger.get_balance(&public_key), ledger.get_history(&public_key)))
+  }).await
+}
+
+
+fn chain_user(query: &str) -> Result<UserData, Error> {
+  chain::find_user(&UserQuery::parse(query))?.ok_or_else(|| Error::not_found(format!("User {query} not found")))
+}
+
+
+/// The chain store is read from files, which would stall the runtime
+async fn blocking<T: Send + 'static, F: FnOnce() -> Result<T, Error> + Send + 'static>(function: F) -> Result<Json<T>, Error> {
+  let result: Result<T, Error> = task::spawn_blocking(function).await.context("The request handler panicked")?;
+  result.map(Json)
+}
@@ -0,0 +1,153 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+  blockchain::{
+    block::Block,
+    data::{Data, r#type::Type, rotation::KeyRotationData, transfer::TransferData, user::UserData},
+    ledger::Entry,
+  },
+  utils::to_hex,
+};
+
+
+#[derive(Clone, Serialize)]
+pub(crate) struct BlockSummary {
+  id: u128,
+  hash: String,
+  timestamp: DateTime<Utc>,
+  r#type: Type,
+  miner: String,
+}
+
+
+/// A block with hexadecimal hashes and its data decoded
+#[derive(Serialize)]
+pub(crate) struct BlockView {
+  id: u128,
+  hash: String,
+  prev_block_hash: String,
+  timestamp: DateTime<Utc>,
+  proof_of_work: String,
+  miner: String,
+  data: DataView,
+}
+
+
+#[derive(Clone, Serialize)]
+pub(crate) struct DataView {
+  timestamp: DateTime<Utc>,
+  public_key: String,
+  miner_amount: f64,
+  #[serde(flatten)]
+  content: Content,
+}
+
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Content {
+  User(UserData),
+  Transfer(TransferData),
+  KeyRotation(KeyRotationData),
+}
+
+
+#[derive(Serialize)]
+pub(crate) struct UserView {
+  user: UserData,
+  balance: f64,
+}
+
+
+#[derive(Serialize)]
+pub(crate) struct HistoryView {
+  public_key: String,
+  balance: f64,
+  history: Vec<Entry>,
+}
+
+
+impl BlockSummary {
+  pub(crate) fn from_block(block: &Block) -> Self {
+    Self {
+      id: block.get_id(),
+      hash: to_hex(&block.get_hash()),
+      timestamp: block.get_timestamp(),
+      r#type: block.get_data_type(),
+      miner: block.get_miner(),
+    }
+  }
+}
+
+
+impl BlockView {
+  pub(crate) fn from_block(block: &Block) -> Result<Self> {
+    Ok(Self {
+      id: block.get_id(),
+      hash: to_hex(&block.get_hash()),
+      prev_block_hash: to_hex(&block.get_prev_block_hash()),
+      timestamp: block.get_timestamp(),
+      proof_of_work: to_hex(&block.get_proof_of_work()),
+      miner: block.get_miner(),
+      data: DataView::from_data(&block.get_signed_data())?,
+    })
+  }
+}
+
+
+impl DataView {
+  pub(crate) fn from_data(data: &Data) -> Result<Self> {
+    let content: Content = match data.get_type() {
+      Type::User => Content::User(serde_json::from_slice(&data.get_data())?),
+      Type::Transfer => Content::Transfer(serde_json::from_slice(&data.get_data())?),
+      Type::KeyRotation => Content::KeyRotation(serde_json::from_slice(&data.get_data())?),
+    };
+
+    Ok(Self {
+      timestamp: data.get_timestamp(),
+      public_key: data.get_public_key(),
+      miner_amount: data.get_miner_amount(),
+      content,
+    })
+  }
+}
+
+
+impl UserView {
+  pub(crate) fn new(user: UserData, balance: f64) -> Self {
+    Self {
+      user,
+      balance,
+    }
+  }
+}
+
+
+impl HistoryView {
+  pub(crate) fn new(public_key: String, balance: f64, history: Vec<Entry>) -> Self {
+    Self {
+      public_key,
+      balance,
+      history,
+    }
+  }
+}
@@ -0,0 +1,50 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{fs::File, io::stderr, sync::Arc};
+
+use anyhow::Result;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::{config::LogConfig, utils::data_path};
+
+
+/// Logs to stderr and, when enabled, as JSON lines to `<DATA_DIR>/system.log`.
+/// The verbosity flag raises the configured level, targets are the module paths like `system::net`
+pub(crate) fn init_logging(config: &LogConfig, verbosity: u8) -> Result<()> {
+  let directives: String = match verbosity {
+    0 => config.level.clone(),
+    1 => format!("{},system=debug", config.level),
+    2 => format!("{},system=debug,libp2p=debug", config.level),
+    _ => String::from("trace"),
+  };
+
+  let console_layer = fmt::layer()
+  .with_writer(stderr)
+  .with_filter(EnvFilter::try_new(&directives)?);
+
+  let file_layer = match config.json_file {
+    true => {
+      let file: File = File::options().create(true).append(true).open(data_path("")?.join("system.log"))?;
+      Some(fmt::layer().json().with_writer(Arc::new(file)).with_filter(EnvFilter::try_new(&directives)?))
+    },
+    false => None,
+  };
+
+  tracing_subscriber::registry().with(console_layer).with(file_layer).try_init()?;
+  Ok(())
+}
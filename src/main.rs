@@ -15,58 +15,362 @@
 //!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 
+mod cli;
+mod config;
+mod explorer;
+mod logging;
 mod ui;
 mod user;
 mod blockchain;
 mod net;
+mod rpc;
 mod utils;
 
 
-use std::io::{stdin, stdout, Write};
+use std::{path::PathBuf, process::ExitCode};
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use ssh_key::{PrivateKey, PublicKey, Algorithm, LineEnding, rand_core::OsRng};
 use tokio::task;
+use tracing::{error, warn};
 
-use crate::{ui::UI, net::server::server_main};
+use crate::{
+  blockchain::{Blockchain, block::Block, chain, data::user::UserData, ledger::Ledger, user_record::UserQuery},
+  cli::{
+    Cli, Command, ClientArgs, NodeArgs, KeygenArgs, PeersArgs, PeersCommand, RpcArgs,
+    TransferArgs, UserArgs, UserCommand, ChainArgs, ChainCommand, ExportArgs, ImportArgs,
+  },
+  config::Config,
+  logging::init_logging,
+  rpc::{serve, Endpoint},
+  ui::{password::{new_password, PasswordSource}, UI},
+  user::{backup::{export_backup, export_key, Import}, keystore::{self, account_path, accounts, key_path, select_account, selected_account}, User},
+  net::{
+    ban_list::{ban_duration, Ban, BanList},
+    config::NetConfig,
+    peer_cache::PeerCache,
+    role::Role,
+    server::server_main,
+    to_keypair,
+  },
+  utils::{data_path, set_data_dir, to_hex},
+};
 
 
 
 #[tokio::main]
-async fn main() {
-  loop {
-    match main_loop() {
-      Ok(_) => break,
-      Err(error) => eprintln!("CRITICAL ERROR: {error}"),
-    }
+async fn main() -> ExitCode {
+  match run(Cli::parse()).await {
+    Ok(_) => ExitCode::SUCCESS,
+    Err(error) => {
+      eprintln!("CRITICAL ERROR: {error}");
+      ExitCode::FAILURE
+    },
+  }
+}
+
+
+async fn run(cli: Cli) -> Result<()> {
+  if let Some(data_dir) = &cli.data_dir {
+    set_data_dir(data_dir.clone())?;
+  }
+
+  let config: Config = Config::load(cli.config)?;
+  if let (None, Some(data_dir)) = (&cli.data_dir, &config.data_dir) {
+    set_data_dir(data_dir.clone())?;
+  }
+  init_logging(&config.log, cli.verbose)?;
+  if let Some(account) = cli.account {
+    select_account(account)?;
+  }
+  let password: PasswordSource = PasswordSource::new(cli.password_file, cli.password_command);
+
+  match cli.command.unwrap_or_default() {
+    Command::Node(args) => node(args, config).await,
+    Command::Client(args) => client(args, config, &password),
+    Command::Keygen(args) => keygen(args),
+    Command::Peers(args) => peers(args),
+    Command::Rpc(args) => rpc(args, config, &password).await,
+    Command::Balance => balance(),
+    Command::Transfer(args) => transfer(args, config, &password),
+    Command::User(args) => user(args, config, &password),
+    Command::Chain(args) => chain(args),
+    Command::Password => change_password(&password),
+    Command::Accounts => list_accounts(),
+    Command::Export(args) => export(args),
+    Command::Import(args) => import(args, &password),
   }
 }
 
 
-fn main_loop() -> Result<()> {
-  if is_server_needed()? {
+async fn node(args: NodeArgs, mut config: Config) -> Result<()> {
+  let key_path: PathBuf = match args.key {
+    Some(path) => path,
+    None => data_path("")?.join("node_key.pem"),
+  };
+
+  let key: PrivateKey = if key_path.exists() {
+    PrivateKey::read_openssh_file(&key_path)?
+  } else {
+    warn!(path = %key_path.display(), "The node key was not found, a temporary key will be used");
+    PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?
+  };
+
+  config.network.apply_args(&args.net);
+  config.network.listen_addrs()?;
+  server_main(key, config.network, args.role).await
+}
+
+
+fn client(args: ClientArgs, mut config: Config, password: &PasswordSource) -> Result<()> {
+  config.network.apply_args(&args.net);
+
+  if args.with_node {
+    let node_config: NetConfig = config.network.ephemeral();
+    // Nobody waits for the node, so its errors are only logged and the client goes on without it
     task::spawn(async {
-      server_main().await?;
-      anyhow::Ok(())
+      let result: Result<()> = match PrivateKey::random(&mut OsRng, Algorithm::Ed25519) {
+        Ok(key) => server_main(key, node_config, Role::Full).await,
+        Err(error) => Err(error.into()),
+      };
+      if let Err(error) = result {
+        error!(%error, "The background node stopped");
+      }
     });
   }
 
-  let mut ui: UI = UI::create()?;
-
+  // Login failures end the client, a wrong password is retried by the prompt itself
+  let mut ui: UI = UI::create(&config, password)?;
   loop {
-    ui.show_menu()?;
-    ui.process_action()?;
+    match client_loop(&mut ui) {
+      Ok(true) => (),
+      Ok(false) => break,
+      Err(error) => error!(%error, "The action failed"),
+    }
+  }
+
+  // The blocks mined right before the end of the input may still wait for a peer
+  if !ui.flush()? {
+    warn!("No peer took the blocks in time, they are only stored locally");
+  }
+  Ok(())
+}
+
+
+/// `false` when the input ended
+fn client_loop(ui: &mut UI) -> Result<bool> {
+  ui.show_menu()?;
+  ui.process_action()
+}
+
+
+async fn rpc(args: RpcArgs, mut config: Config, password: &PasswordSource) -> Result<()> {
+  config.network.apply_args(&args.net);
+  let endpoint: Endpoint = match args.socket {
+    Some(path) => Endpoint::Unix(path),
+    None => {
+      // There is no authentication, anyone reaching the port could spend the money of the user
+      ensure!(args.rpc_listen.ip().is_loopback(), "The JSON-RPC API can only listen on a loopback address, use --socket to share it");
+      Endpoint::Tcp(args.rpc_listen)
+    },
+  };
+  let user: User = login(&config, password)?;
+  serve(endpoint, user).await
+}
+
+
+fn balance() -> Result<()> {
+  // The public key is stored unencrypted, no password is needed
+  let key: PrivateKey = PrivateKey::read_openssh_file(&user_key_path()?)?;
+  println!("{}", Ledger::load()?.get_balance(key.public_key()));
+  Ok(())
+}
+
+
+fn transfer(args: TransferArgs, mut config: Config, password: &PasswordSource) -> Result<()> {
+  config.network.apply_args(&args.net);
+  let user: User = login(&config, password)?;
+  user.wait_for_peers()?;
+  let recipient: UserData = user.find_user(&UserQuery::parse(&args.to))?;
+  let block: Block = user.transfer(&PublicKey::from_openssh(&recipient.get_public_key())?, args.amount)?;
+
+  println!("Block: {}", block.get_id());
+  println!("Hash: {}", to_hex(&block.get_hash()));
+  if !user.flush()? {
+    warn!("No peer took the block in time, it is only stored locally");
+  }
+  Ok(())
+}
+
+
+fn user(args: UserArgs, mut config: Config, password: &PasswordSource) -> Result<()> {
+  match args.command {
+    UserCommand::Show(args) => {
+      config.network.apply_args(&args.net);
+      // Looking users up needs no identity, the key only identifies this run in the network
+      let key: PrivateKey = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+      let blockchain: Blockchain = Blockchain::from_key(&key, &config)?;
+      blockchain.wait_for_peers()?;
+      let user_data: UserData = blockchain.find_user(&UserQuery::parse(&args.query))?;
+
+      println!("User name: {}", user_data.get_user_name());
+      println!("Name: {} {}", user_data.get_first_name(), user_data.get_last_name());
+      println!("Public key: {}", user_data.get_public_key());
+    },
+
+    UserCommand::RotateKey(args) => {
+      config.network.apply_args(&args.net);
+      user_key_path()?;
+      let (key, password): (PrivateKey, String) = password.unlock_with_password()?;
+      let mut user: User = User::from_key(key, &config)?;
+      user.wait_for_peers()?;
+      let block: Block = user.rotate_key(&password)?;
+
+      println!("Block: {}", block.get_id());
+      println!("Public key: {}", user.get_key().public_key().to_openssh()?);
+      if !user.flush()? {
+        warn!("No peer took the block in time, it is only stored locally");
+      }
+    },
   }
+  Ok(())
 }
 
 
-fn is_server_needed() -> Result<bool> {
-  let mut answer: String = String::new();
-  print!("Do you want to run the server in addition to the main program? [Y/n]: ");
-  stdout().flush()?;
-  stdin().read_line(&mut answer)?;
-  Ok(match answer.to_lowercase().trim() {
-    "y" => true,
-    "yes" => true,
-    _ => false,
-  })
+fn chain(args: ChainArgs) -> Result<()> {
+  match args.command {
+    ChainCommand::Verify => {
+      let height: u128 = chain::verify()?;
+      println!("The chain of {height} blocks is valid");
+    },
+  }
+  Ok(())
+}
+
+
+fn change_password(password: &PasswordSource) -> Result<()> {
+  user_key_path()?;
+  let key: PrivateKey = password.unlock()?;
+  keystore::replace_key(&key, &new_password()?)?;
+  println!("The password was changed");
+  Ok(())
+}
+
+
+/// Logs the local user in for the commands without the menu
+fn login(config: &Config, password: &PasswordSource) -> Result<User> {
+  user_key_path()?;
+  User::from_key(password.unlock()?, config)
+}
+
+
+/// The only account is used when none was selected
+fn user_key_path() -> Result<PathBuf> {
+  if selected_account().is_none() {
+    match accounts()?.as_slice() {
+      [] => bail!("No user in the data directory, register with the client first"),
+      [account] => select_account(account.clone())?,
+      accounts => bail!("The data directory has the accounts {}, choose one with --account", accounts.join(", ")),
+    }
+  }
+
+  let path: PathBuf = key_path()?;
+  if !path.exists() {
+    bail!("The account {} doesn't exist, register it with the client first", selected_account().unwrap_or_default());
+  }
+  Ok(path)
+}
+
+
+fn list_accounts() -> Result<()> {
+  for account in accounts()? {
+    // The public key is stored unencrypted
+    let public_key: String = PrivateKey::read_openssh_file(&account_path(&account)?)?.public_key().to_openssh()?;
+    println!("{account} {public_key}");
+  }
+  Ok(())
+}
+
+
+fn export(args: ExportArgs) -> Result<()> {
+  user_key_path()?;
+  if args.backup {
+    export_backup(&args.output)?;
+  } else {
+    export_key(&args.output)?;
+  }
+  println!("Exported {} to {}", selected_account().unwrap_or_default(), args.output.display());
+  Ok(())
+}
+
+
+/// The password is checked before the account is stored, a forgotten password is found out on the old machine
+fn import(args: ImportArgs, password: &PasswordSource) -> Result<()> {
+  let import: Import = Import::read(&args.input)?;
+  let account: String = selected_account().unwrap_or_else(|| import.get_account());
+  if !import.check_password(&password.read()?)? {
+    bail!("Wrong password");
+  }
+
+  import.save(&account)?;
+  println!("Imported {account} {}", import.get_public_key().to_openssh()?);
+  Ok(())
+}
+
+
+fn keygen(args: KeygenArgs) -> Result<()> {
+  let path: PathBuf = match args.output {
+    Some(path) => path,
+    None => data_path("")?.join("node_key.pem"),
+  };
+
+  if path.exists() && !args.force {
+    bail!("The key {} already exists, use --force to overwrite it", path.display());
+  }
+
+  let key: PrivateKey = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+  key.write_openssh_file(&path, LineEnding::LF)?;
+
+  println!("Key: {}", path.display());
+  println!("Peer ID: {}", to_keypair(&key)?.public().to_peer_id());
+  Ok(())
+}
+
+
+fn peers(args: PeersArgs) -> Result<()> {
+  let mut ban_list: BanList = BanList::from_default_path()?;
+  ban_list.prune();
+
+  match args.command {
+    PeersCommand::List => {
+      println!("Banned peers:");
+      for (peer_id, ban) in &ban_list.peers {
+        println!("  {peer_id} {ban}");
+      }
+      println!("Banned addresses:");
+      for (ip, ban) in &ban_list.ips {
+        println!("  {ip} {ban}");
+      }
+      println!("Known peers:");
+      for (peer_id, peer) in PeerCache::from_default_path()?.peers {
+        println!("  {peer_id} last seen {}", peer.last_seen);
+      }
+    },
+
+    PeersCommand::Ban(args) => {
+      let ban: Ban = Ban::create(args.reason, args.duration.map(ban_duration).transpose()?)?;
+      println!("Banned {} {ban}", args.target);
+      ban_list.ban(args.target, ban);
+    },
+
+    PeersCommand::Unban(args) => {
+      if !ban_list.unban(&args.target) {
+        bail!("{} is not banned", args.target);
+      }
+      println!("Unbanned {}", args.target);
+    },
+  }
+
+  ban_list.save_default()
 }
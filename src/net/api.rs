@@ -16,26 +16,39 @@
 
 
 use anyhow::Result;
+use std::{
+  sync::mpsc::{self, Receiver},
+  thread::sleep,
+  time::{Duration, Instant},
+};
+
+use libp2p::{kad::{Record, RecordKey}, PeerId};
 use serde::Serialize;
-use tokio::{task::JoinHandle, sync::watch::Sender};
+use tokio::{task::JoinHandle, sync::mpsc::UnboundedSender};
 
 use crate::{
   blockchain::{block::Block, data::Data},
-  net::send_data::SendData,
+  net::{
+    request::{Request, RecordSender, PeersSender, FlushSender},
+    send_data::SendData,
+    validation::{BLOCKS_DATA_TOPIC, BLOCKS_TOPIC},
+  },
 };
 
 
 pub(crate) struct API {
   net_handle: JoinHandle<Result<()>>,
-  sender: Sender<SendData>,
+  sender: UnboundedSender<SendData>,
+  requests: UnboundedSender<Request>,
 }
 
 
 impl API {
-  pub(crate) fn new(net_handle: JoinHandle<Result<()>>, sender: Sender<SendData>) -> Self {
+  pub(crate) fn new(net_handle: JoinHandle<Result<()>>, sender: UnboundedSender<SendData>, requests: UnboundedSender<Request>) -> Self {
     Self {
       net_handle,
       sender,
+      requests,
     }
   }
 
@@ -48,13 +61,55 @@ impl API {
 
 
   pub(crate) fn send_block(&self, block: &Block) -> Result<()> {
-    self.send("blocks", block)?;
+    self.send(BLOCKS_TOPIC, block)?;
     Ok(())
   }
 
 
   pub(crate) fn send_block_data(&self, block_data: &Data) -> Result<()> {
-    self.send("blocks_data", block_data)?;
+    self.send(BLOCKS_DATA_TOPIC, block_data)?;
+    Ok(())
+  }
+
+
+  pub(crate) fn put_record(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+    self.requests.send(Request::PutRecord(Record::new(RecordKey::new(&key), value)))?;
     Ok(())
   }
+
+
+  /// Blocks until the DHT query finishes, it is bounded by the Kademlia query timeout
+  pub(crate) fn get_record(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let (sender, receiver): (RecordSender, Receiver<Option<Vec<u8>>>) = mpsc::channel();
+    self.requests.send(Request::GetRecord(RecordKey::new(&key), sender))?;
+    Ok(receiver.recv().ok().flatten())
+  }
+
+
+  pub(crate) fn list_peers(&self) -> Result<Vec<PeerId>> {
+    let (sender, receiver): (PeersSender, Receiver<Vec<PeerId>>) = mpsc::channel();
+    self.requests.send(Request::ListPeers(sender))?;
+    Ok(receiver.recv()?)
+  }
+
+
+  /// Polls the connections, `false` when nobody connected before the timeout
+  pub(crate) fn wait_for_peers(&self, timeout: Duration) -> Result<bool> {
+    let start: Instant = Instant::now();
+    while self.list_peers()?.is_empty() {
+      if start.elapsed() >= timeout {
+        return Ok(false);
+      }
+      sleep(Duration::from_millis(100));
+    }
+    Ok(true)
+  }
+
+
+  /// Waits until the sent blocks and block data reach the network, `false` when the timeout runs out first
+  pub(crate) fn flush(&self, timeout: Duration) -> Result<bool> {
+    let (sender, receiver): (FlushSender, Receiver<()>) = mpsc::channel();
+    self.requests.send(Request::Flush(sender))?;
+    Ok(receiver.recv_timeout(timeout).is_ok())
+  }
 }
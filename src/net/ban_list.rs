@@ -0,0 +1,236 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  fmt::{self, Display, Formatter},
+  fs::{rename, File},
+  str::FromStr,
+  net::IpAddr,
+  path::{Path, PathBuf},
+  collections::HashMap,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Serialize, Deserialize};
+use libp2p::PeerId;
+
+use crate::utils::data_path;
+
+
+/// Peers and IP addresses that are not allowed to connect, shared by the running node and the `peers` admin commands
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct BanList {
+  pub(crate) peers: HashMap<PeerId, Ban>,
+  pub(crate) ips: HashMap<IpAddr, Ban>,
+  #[serde(skip)]
+  offences: HashMap<PeerId, u32>,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Ban {
+  pub(crate) reason: String,
+  /// `None` bans forever
+  pub(crate) until: Option<DateTime<Utc>>,
+}
+
+
+/// A peer ID or an IP address given to the `peers` admin commands
+#[derive(Clone)]
+pub(crate) enum BanTarget {
+  Peer(PeerId),
+  Ip(IpAddr),
+}
+
+
+/// Negative durations would create bans that have already expired
+pub(crate) fn ban_duration(seconds: i64) -> Result<TimeDelta> {
+  ensure!(seconds >= 0, "The ban duration can't be negative");
+  TimeDelta::try_seconds(seconds).context("The ban duration is too long")
+}
+
+
+impl BanList {
+  fn default_path() -> Result<PathBuf> {
+    Ok(data_path("")?.join("ban_list.json"))
+  }
+
+
+  pub(crate) fn from_default_path() -> Result<Self> {
+    let default_path: PathBuf = Self::default_path()?;
+    if !default_path.exists() {
+      return Ok(Self::default());
+    }
+    Self::from_path(default_path)
+  }
+
+
+  pub(crate) fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let file: File = File::options().truncate(false).read(true).open(path)?;
+    Ok(serde_json::from_reader(file)?)
+  }
+
+
+  /// Picks up the changes made by the admin commands while keeping the offence counters
+  pub(crate) fn reload(&mut self) -> Result<()> {
+    let ban_list: Self = Self::from_default_path()?;
+    self.peers = ban_list.peers;
+    self.ips = ban_list.ips;
+    self.prune();
+    Ok(())
+  }
+
+
+  pub(crate) fn ban(&mut self, target: BanTarget, ban: Ban) {
+    match target {
+      BanTarget::Peer(peer_id) => self.ban_peer(peer_id, ban),
+      BanTarget::Ip(ip) => self.ban_ip(ip, ban),
+    }
+  }
+
+
+  pub(crate) fn unban(&mut self, target: &BanTarget) -> bool {
+    match target {
+      BanTarget::Peer(peer_id) => self.unban_peer(peer_id),
+      BanTarget::Ip(ip) => self.unban_ip(ip),
+    }
+  }
+
+
+  pub(crate) fn ban_peer(&mut self, peer_id: PeerId, ban: Ban) {
+    self.peers.insert(peer_id, ban);
+  }
+
+
+  pub(crate) fn ban_ip(&mut self, ip: IpAddr, ban: Ban) {
+    self.ips.insert(ip, ban);
+  }
+
+
+  pub(crate) fn unban_peer(&mut self, peer_id: &PeerId) -> bool {
+    self.offences.remove(peer_id);
+    self.peers.remove(peer_id).is_some()
+  }
+
+
+  pub(crate) fn unban_ip(&mut self, ip: &IpAddr) -> bool {
+    self.ips.remove(ip).is_some()
+  }
+
+
+  /// Counts a misbehaviour of the peer, returns the ban to add once it reaches `max_offences`
+  pub(crate) fn record_offence(&mut self, peer_id: PeerId, max_offences: u32, duration: TimeDelta) -> Result<Option<Ban>> {
+    let offences: &mut u32 = self.offences.entry(peer_id).or_default();
+    *offences += 1;
+    if *offences < max_offences {
+      return Ok(None);
+    }
+    self.offences.remove(&peer_id);
+    Ok(Some(Ban::create("Too many invalid messages", Some(duration))?))
+  }
+
+
+  /// Reloads the file first, so the bans added by the admin commands since the last reload are kept.
+  /// A file that can't be read isn't overwritten, the ban only holds in memory then
+  pub(crate) fn add_and_save(&mut self, target: BanTarget, ban: Ban) -> Result<()> {
+    let reloaded: Result<()> = self.reload();
+    self.ban(target, ban);
+    reloaded?;
+    self.save_default()
+  }
+
+
+  /// Drops the expired bans
+  pub(crate) fn prune(&mut self) {
+    self.peers.retain(|_, ban: &mut Ban| !ban.is_expired());
+    self.ips.retain(|_, ban: &mut Ban| !ban.is_expired());
+  }
+
+
+  pub(crate) fn save_default(&self) -> Result<()> {
+    self.save(Self::default_path()?)
+  }
+
+
+  /// Renamed into place, so the node and the admin commands never read a half written file
+  pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    let new_path: PathBuf = path.as_ref().with_extension("json.new");
+    let file: File = File::options().create(true).truncate(true).write(true).open(&new_path)?;
+    serde_json::to_writer_pretty(file, self)?;
+    rename(new_path, path)?;
+    Ok(())
+  }
+}
+
+
+impl Ban {
+  fn new(reason: String, until: Option<DateTime<Utc>>) -> Self {
+    Self {
+      reason,
+      until,
+    }
+  }
+
+
+  pub(crate) fn create<R: Into<String>>(reason: R, duration: Option<TimeDelta>) -> Result<Self> {
+    let until: Option<DateTime<Utc>> = duration.map(|duration: TimeDelta| {
+      Utc::now().checked_add_signed(duration).context("The ban would end after the last supported date")
+    }).transpose()?;
+    Ok(Self::new(reason.into(), until))
+  }
+
+
+  pub(crate) fn is_expired(&self) -> bool {
+    self.until.is_some_and(|until: DateTime<Utc>| until <= Utc::now())
+  }
+}
+
+
+impl Display for Ban {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.until {
+      Some(until) => write!(f, "until {until}: {}", self.reason),
+      None => write!(f, "forever: {}", self.reason),
+    }
+  }
+}
+
+
+impl FromStr for BanTarget {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+      return Ok(Self::Ip(ip));
+    }
+    if let Ok(peer_id) = s.parse::<PeerId>() {
+      return Ok(Self::Peer(peer_id));
+    }
+    bail!("{s} is neither a peer ID nor an IP address");
+  }
+}
+
+
+impl Display for BanTarget {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Peer(peer_id) => write!(f, "{peer_id}"),
+      Self::Ip(ip) => write!(f, "{ip}"),
+    }
+  }
+}
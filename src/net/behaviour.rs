@@ -15,76 +15,136 @@
 //!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 
-use std::time::Duration;
-
-use anyhow::Result;
+use anyhow::{Error, Result};
 use libp2p::{
+  autonat,
+  connection_limits::{self, ConnectionLimits},
+  dcutr,
   gossipsub::{self, MessageAuthenticity, ValidationMode},
   identify,
   identity::{Keypair, PublicKey},
-  kad::{self, store::MemoryStore, PROTOCOL_NAME},
-  swarm::NetworkBehaviour,
+  kad,
+  mdns,
+  relay,
+  swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
   PeerId,
 };
 
+use crate::net::{
+  config::NetConfig,
+  firewall::Firewall,
+  protocol::{agent_version, gossipsub_protocol_prefix, identify_protocol, kademlia_protocol},
+  record_store::DiskStore,
+  role::Role,
+  validation::{peer_score_params, peer_score_thresholds},
+};
+
 
 #[derive(NetworkBehaviour)]
 pub(crate) struct Behaviour {
+  pub(crate) firewall: Firewall,
+  pub(crate) limits: connection_limits::Behaviour,
   pub(crate) gossipsub: gossipsub::Behaviour,
   pub(crate) identify: identify::Behaviour,
-  pub(crate) kademlia: kad::Behaviour<MemoryStore>,
+  pub(crate) kademlia: kad::Behaviour<DiskStore>,
+  pub(crate) mdns: Toggle<mdns::tokio::Behaviour>,
+  pub(crate) relay: Toggle<relay::Behaviour>,
+  pub(crate) relay_client: relay::client::Behaviour,
+  pub(crate) autonat: autonat::Behaviour,
+  pub(crate) dcutr: Toggle<dcutr::Behaviour>,
 }
 
 
 impl Behaviour {
-  fn new(
-    gossipsub: gossipsub::Behaviour,
-    identify: identify::Behaviour,
-    kademlia: kad::Behaviour<MemoryStore>,
-  ) -> Self {
-    Self {
-      gossipsub,
-      identify,
-      kademlia,
-    }
-  }
-
-
-  pub(crate) fn from_key(key: Keypair) -> Result<Self> {
+  /// Servers also get the circuit relay server and the persistent record store, the relay client comes from the transport built by the swarm builder
+  pub(crate) fn from_key(key: Keypair, config: &NetConfig, relay_client: relay::client::Behaviour, role: Role) -> Result<Self> {
     let publick_key: PublicKey = key.public();
     let peer_id: PeerId = publick_key.to_peer_id();
 
     let gossipsub_behaviour: gossipsub::Behaviour = {
       let gossipsub_config: gossipsub::Config = gossipsub::ConfigBuilder::default()
+      .protocol_id_prefix(gossipsub_protocol_prefix(&config.chain_id))
       .validation_mode(ValidationMode::Strict)
+      .heartbeat_interval(config.timing.gossipsub_heartbeat())
+      .validate_messages()
       .build()?;
       let privacy: MessageAuthenticity = MessageAuthenticity::Signed(key.clone());
-      gossipsub::Behaviour::new(privacy, gossipsub_config).unwrap()
+      let mut gossipsub_behaviour: gossipsub::Behaviour = gossipsub::Behaviour::new(privacy, gossipsub_config).unwrap();
+      gossipsub_behaviour.with_peer_score(peer_score_params(), peer_score_thresholds()).map_err(Error::msg)?;
+      gossipsub_behaviour
     };
 
     let identify_behaviour: identify::Behaviour = {
       let identify_config: identify::Config = identify::Config::new(
-        String::from("polkadot/1.0.0"),
+        identify_protocol(&config.chain_id),
         publick_key,
       )
-      .with_interval(Duration::from_secs(1));
+      .with_agent_version(agent_version())
+      .with_interval(config.timing.identify_interval());
 
       identify::Behaviour::new(identify_config)
     };
 
-    let kademlia_behaviour: kad::Behaviour<MemoryStore> = {
-      let mut kademlia_config: kad::Config = kad::Config::new(PROTOCOL_NAME);
-      kademlia_config.set_query_timeout(Duration::from_secs(u64::MAX));
-      kademlia_config.set_publication_interval(Some(Duration::from_secs(1)));
-      kademlia_config.set_replication_interval(Some(Duration::from_secs(1)));
-      let store: MemoryStore = MemoryStore::new(peer_id);
+    let kademlia_behaviour: kad::Behaviour<DiskStore> = {
+      let mut kademlia_config: kad::Config = kad::Config::new(kademlia_protocol(&config.chain_id)?);
+      kademlia_config.set_query_timeout(config.timing.kademlia_query_timeout());
+      kademlia_config.set_publication_interval(Some(config.timing.kademlia_publication_interval()));
+      kademlia_config.set_replication_interval(Some(config.timing.kademlia_replication_interval()));
+      kademlia_config.set_periodic_bootstrap_interval(None);
+      kademlia_config.set_record_filtering(kad::StoreInserts::FilterBoth);
+      kademlia_config.set_record_ttl(Some(config.records.ttl()));
+      // Only nodes keep records across restarts, a client shares the data directory with its embedded node
+      let store: DiskStore = if role.is_server() && config.records.persist {
+        DiskStore::from_default_path(peer_id, &config.records)?
+      } else {
+        DiskStore::new(peer_id, &config.records, None)
+      };
       kad::Behaviour::with_config(peer_id, store, kademlia_config)
     };
 
-    Ok(Self::new(
-      gossipsub_behaviour,
-      identify_behaviour,
-      kademlia_behaviour,
-    ))
+    let mdns_behaviour: Toggle<mdns::tokio::Behaviour> = match config.mdns {
+      true => Some(mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?).into(),
+      false => None.into(),
+    };
+
+    let relay_behaviour: Toggle<relay::Behaviour> = match role.is_server() && config.nat.relay_server {
+      true => Some(relay::Behaviour::new(peer_id, relay::Config::default())).into(),
+      false => None.into(),
+    };
+
+    let autonat_behaviour: autonat::Behaviour = autonat::Behaviour::new(peer_id, autonat::Config::default());
+
+    let dcutr_behaviour: Toggle<dcutr::Behaviour> = match config.nat.hole_punching {
+      true => Some(dcutr::Behaviour::new(peer_id)).into(),
+      false => None.into(),
+    };
+
+    let limits_behaviour: connection_limits::Behaviour = connection_limits::Behaviour::new(
+      ConnectionLimits::default()
+      .with_max_established(Some(config.limits.max_connections))
+      .with_max_established_per_peer(Some(config.limits.max_connections_per_peer))
+      .with_max_pending_incoming(Some(config.limits.max_pending_incoming))
+    );
+
+    Ok(Self {
+      firewall: Firewall::create(config.limits.max_connections_per_ip),
+      limits: limits_behaviour,
+      gossipsub: gossipsub_behaviour,
+      identify: identify_behaviour,
+      kademlia: kademlia_behaviour,
+      mdns: mdns_behaviour,
+      relay: relay_behaviour,
+      relay_client,
+      autonat: autonat_behaviour,
+      dcutr: dcutr_behaviour,
+    })
+  }
+
+
+  /// Bootstraps Kademlia and looks up a random peer to discover the rest of the network
+  pub(crate) fn refresh_routing_table(&mut self) {
+    if self.kademlia.bootstrap().is_ok() {
+      self.kademlia.get_closest_peers(PeerId::random());
+    }
   }
 }
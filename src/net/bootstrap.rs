@@ -0,0 +1,108 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+use libp2p::{
+  multiaddr::Protocol,
+  swarm::Swarm,
+  Multiaddr,
+  PeerId,
+};
+use tracing::debug;
+
+use crate::net::{
+  behaviour::Behaviour,
+  config::BootstrapConfig,
+  peer_cache::PeerCache,
+  server_list::{ServerList, SERVER_TTL},
+  split_peer_addr,
+};
+
+
+/// Every known way into the network: configured peers and DNS seeds, the local server list and the peer cache
+pub(crate) struct Bootstrap {
+  peers: Vec<(PeerId, Multiaddr)>,
+  dns_seeds: Vec<Multiaddr>,
+}
+
+
+impl Bootstrap {
+  fn new(peers: Vec<(PeerId, Multiaddr)>, dns_seeds: Vec<Multiaddr>) -> Self {
+    Self {
+      peers,
+      dns_seeds,
+    }
+  }
+
+
+  pub(crate) fn from_config(config: &BootstrapConfig) -> Result<Self> {
+    let mut peers: Vec<(PeerId, Multiaddr)> = Vec::new();
+    let mut dns_seeds: Vec<Multiaddr> = Vec::new();
+
+    for addr in &config.peers {
+      peers.push(split_peer_addr(addr)?);
+    }
+    dns_seeds.extend(config.dns_seeds.iter().map(String::as_str).map(dnsaddr));
+
+    if let Ok(mut server_list) = ServerList::from_default_path() {
+      server_list.prune(SERVER_TTL);
+      for (peer_id, server) in server_list.servers {
+        peers.extend(server.addresses.into_iter().map(|addr: Multiaddr| (peer_id, addr)));
+      }
+    }
+
+    if config.peer_cache_size > 0 {
+      if let Ok(peer_cache) = PeerCache::from_default_path() {
+        for (peer_id, peer) in peer_cache.peers {
+          peers.extend(peer.addresses.into_iter().map(|addr: Multiaddr| (peer_id, addr)));
+        }
+      }
+    }
+
+    Ok(Self::new(peers, dns_seeds))
+  }
+
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.peers.is_empty() && self.dns_seeds.is_empty()
+  }
+
+
+  /// Adds the known peers to Kademlia and dials the DNS seeds, whose peers join the routing table through identify
+  pub(crate) fn start(&self, swarm: &mut Swarm<Behaviour>) -> Result<()> {
+    debug!(peers = self.peers.len(), dns_seeds = self.dns_seeds.len(), "Joining the network");
+    let local_peer_id: PeerId = *swarm.local_peer_id();
+    for (peer_id, addr) in self.peers.iter().filter(|(peer_id, _)| *peer_id != local_peer_id) {
+      swarm.behaviour_mut().kademlia.add_address(peer_id, addr.clone());
+    }
+
+    for addr in &self.dns_seeds {
+      swarm.dial(addr.clone())?;
+    }
+
+    // Fails only when no peer is known yet, Kademlia bootstraps by itself once the first one is added
+    swarm.behaviour_mut().kademlia.bootstrap().ok();
+    Ok(())
+  }
+}
+
+
+fn dnsaddr(host: &str) -> Multiaddr {
+  Multiaddr::empty().with(Protocol::Dnsaddr(Cow::Owned(host.to_string())))
+}
@@ -0,0 +1,347 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+  time::Duration,
+};
+
+use anyhow::{bail, Result};
+use chrono::TimeDelta;
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+use libp2p::{multiaddr::Protocol, Multiaddr};
+
+use crate::{cli::NetArgs, net::ban_list::ban_duration};
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NetConfig {
+  /// Identifies the network, nodes of different chains do not talk to each other
+  pub(crate) chain_id: String,
+  /// Explicit listen addresses, when set the transport and port options are ignored
+  pub(crate) listen_addrs: Vec<Multiaddr>,
+  /// Addresses announced to other peers in addition to the observed ones
+  pub(crate) external_addrs: Vec<Multiaddr>,
+  pub(crate) tcp: bool,
+  pub(crate) quic: bool,
+  pub(crate) ipv4: bool,
+  pub(crate) ipv6: bool,
+  pub(crate) tcp_port: u16,
+  pub(crate) quic_port: u16,
+  /// Discover peers on the local network with mDNS
+  pub(crate) mdns: bool,
+  /// Serve Prometheus metrics of a node over HTTP on this address
+  pub(crate) metrics: Option<SocketAddr>,
+  /// Serve the read-only block explorer of a full node over HTTP on this address
+  pub(crate) explorer: Option<SocketAddr>,
+  pub(crate) bootstrap: BootstrapConfig,
+  pub(crate) nat: NatConfig,
+  pub(crate) limits: LimitsConfig,
+  pub(crate) records: RecordsConfig,
+  pub(crate) timing: TimingConfig,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct BootstrapConfig {
+  /// Peers to join the network through, every address must end with `/p2p/<PEER_ID>`
+  pub(crate) peers: Vec<Multiaddr>,
+  /// Hosts publishing `dnsaddr=<MULTIADDR>` TXT records under `_dnsaddr.<HOST>`
+  pub(crate) dns_seeds: Vec<String>,
+  /// How many learned peers are kept in `<DATA_DIR>/peer_cache.json`, 0 disables the cache
+  pub(crate) peer_cache_size: usize,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct NatConfig {
+  /// Serve as a circuit relay and AutoNAT server for other peers when running as a node
+  pub(crate) relay_server: bool,
+  /// Reserve slots on relays when unreachable and upgrade relayed connections with hole punching
+  pub(crate) hole_punching: bool,
+  /// How many relays to keep reservations on
+  pub(crate) max_relays: usize,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct LimitsConfig {
+  pub(crate) max_connections: u32,
+  pub(crate) max_connections_per_peer: u32,
+  pub(crate) max_connections_per_ip: u32,
+  pub(crate) max_pending_incoming: u32,
+  /// Invalid gossipsub messages after which a peer is banned automatically
+  pub(crate) max_invalid_messages: u32,
+  /// How long automatic bans last, in seconds
+  pub(crate) ban_duration: i64,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RecordsConfig {
+  /// Keep the DHT records of a node in `<DATA_DIR>/records.json` across restarts
+  pub(crate) persist: bool,
+  pub(crate) max_records: usize,
+  /// The largest record value that is stored, in bytes
+  pub(crate) max_value_bytes: usize,
+  pub(crate) max_provided_keys: usize,
+  /// How long records live without being republished, in seconds
+  pub(crate) ttl: u64,
+}
+
+
+/// Intervals and timeouts of the network, in seconds, unset values come from the profile
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct TimingConfig {
+  pub(crate) profile: TimingProfile,
+  pub(crate) gossipsub_heartbeat: Option<u64>,
+  pub(crate) identify_interval: Option<u64>,
+  pub(crate) kademlia_query_timeout: Option<u64>,
+  pub(crate) kademlia_publication_interval: Option<u64>,
+  pub(crate) kademlia_replication_interval: Option<u64>,
+  pub(crate) idle_connection_timeout: Option<u64>,
+  /// How often the routing table is refreshed with a bootstrap and a random walk
+  pub(crate) bootstrap_interval: Option<u64>,
+}
+
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TimingProfile {
+  /// Short intervals for small local clusters where changes should show up quickly
+  Dev,
+  /// Intervals close to the libp2p defaults, suitable for a growing public network
+  #[default]
+  Production,
+}
+
+
+impl Default for NetConfig {
+  fn default() -> Self {
+    Self {
+      chain_id: String::from("mainnet"),
+      listen_addrs: Vec::default(),
+      external_addrs: Vec::default(),
+      tcp: true,
+      quic: true,
+      ipv4: true,
+      ipv6: true,
+      tcp_port: 0,
+      quic_port: 0,
+      mdns: false,
+      metrics: None,
+      explorer: None,
+      bootstrap: BootstrapConfig::default(),
+      nat: NatConfig::default(),
+      limits: LimitsConfig::default(),
+      records: RecordsConfig::default(),
+      timing: TimingConfig::default(),
+    }
+  }
+}
+
+
+impl Default for BootstrapConfig {
+  fn default() -> Self {
+    Self {
+      peers: Vec::default(),
+      dns_seeds: Vec::default(),
+      peer_cache_size: 64,
+    }
+  }
+}
+
+
+impl Default for NatConfig {
+  fn default() -> Self {
+    Self {
+      relay_server: true,
+      hole_punching: true,
+      max_relays: 2,
+    }
+  }
+}
+
+
+impl Default for LimitsConfig {
+  fn default() -> Self {
+    Self {
+      max_connections: 200,
+      max_connections_per_peer: 2,
+      max_connections_per_ip: 8,
+      max_pending_incoming: 64,
+      max_invalid_messages: 10,
+      ban_duration: 3600,
+    }
+  }
+}
+
+
+impl Default for RecordsConfig {
+  fn default() -> Self {
+    Self {
+      persist: true,
+      max_records: 1024,
+      max_value_bytes: 65 * 1024,
+      max_provided_keys: 1024,
+      ttl: 48 * 60 * 60,
+    }
+  }
+}
+
+
+impl NetConfig {
+  pub(crate) fn apply_args(&mut self, args: &NetArgs) {
+    if let Some(chain_id) = &args.chain_id {
+      self.chain_id = chain_id.clone();
+    }
+    if !args.listen_addrs.is_empty() {
+      self.listen_addrs = args.listen_addrs.clone();
+    }
+    self.external_addrs.extend(args.external_addrs.iter().cloned());
+    self.bootstrap.peers.extend(args.bootstrap_peers.iter().cloned());
+    self.bootstrap.dns_seeds.extend(args.dns_seeds.iter().cloned());
+    self.tcp &= !args.no_tcp;
+    self.quic &= !args.no_quic;
+    self.ipv4 &= !args.no_ipv4;
+    self.ipv6 &= !args.no_ipv6;
+    self.mdns |= args.mdns;
+    if let Some(profile) = args.timing {
+      self.timing.profile = profile;
+    }
+    if let Some(port) = args.tcp_port {
+      self.tcp_port = port;
+    }
+    if let Some(port) = args.quic_port {
+      self.quic_port = port;
+    }
+    if let Some(addr) = args.metrics {
+      self.metrics = Some(addr);
+    }
+    if let Some(addr) = args.explorer {
+      self.explorer = Some(addr);
+    }
+  }
+
+
+  /// The same configuration listening on random ports, for a node running next to the client in one process
+  pub(crate) fn ephemeral(&self) -> Self {
+    Self {
+      listen_addrs: Vec::default(),
+      external_addrs: Vec::default(),
+      tcp_port: 0,
+      quic_port: 0,
+      ..self.clone()
+    }
+  }
+
+
+  pub(crate) fn listen_addrs(&self) -> Result<Vec<Multiaddr>> {
+    if !self.listen_addrs.is_empty() {
+      return Ok(self.listen_addrs.clone());
+    }
+
+    let mut ips: Vec<IpAddr> = Vec::new();
+    if self.ipv4 {
+      ips.push(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+    if self.ipv6 {
+      ips.push(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+    }
+
+    let mut addrs: Vec<Multiaddr> = Vec::new();
+    for ip in ips {
+      if self.tcp {
+        addrs.push(Multiaddr::empty().with(Protocol::from(ip)).with(Protocol::Tcp(self.tcp_port)));
+      }
+      if self.quic {
+        addrs.push(Multiaddr::empty().with(Protocol::from(ip)).with(Protocol::Udp(self.quic_port)).with(Protocol::QuicV1));
+      }
+    }
+
+    if addrs.is_empty() {
+      bail!("No listen addresses, enable at least one transport and one IP version");
+    }
+    Ok(addrs)
+  }
+}
+
+
+impl TimingConfig {
+  pub(crate) fn gossipsub_heartbeat(&self) -> Duration {
+    self.seconds(self.gossipsub_heartbeat, 1, 1)
+  }
+
+
+  pub(crate) fn identify_interval(&self) -> Duration {
+    self.seconds(self.identify_interval, 5, 300)
+  }
+
+
+  pub(crate) fn kademlia_query_timeout(&self) -> Duration {
+    self.seconds(self.kademlia_query_timeout, 30, 60)
+  }
+
+
+  pub(crate) fn kademlia_publication_interval(&self) -> Duration {
+    self.seconds(self.kademlia_publication_interval, 60, 22 * 60 * 60)
+  }
+
+
+  pub(crate) fn kademlia_replication_interval(&self) -> Duration {
+    self.seconds(self.kademlia_replication_interval, 30, 60 * 60)
+  }
+
+
+  pub(crate) fn idle_connection_timeout(&self) -> Duration {
+    self.seconds(self.idle_connection_timeout, 30, 60)
+  }
+
+
+  pub(crate) fn bootstrap_interval(&self) -> Duration {
+    self.seconds(self.bootstrap_interval, 30, 5 * 60)
+  }
+
+
+  fn seconds(&self, value: Option<u64>, dev: u64, production: u64) -> Duration {
+    Duration::from_secs(value.unwrap_or(match self.profile {
+      TimingProfile::Dev => dev,
+      TimingProfile::Production => production,
+    }))
+  }
+}
+
+
+impl RecordsConfig {
+  pub(crate) fn ttl(&self) -> Duration {
+    Duration::from_secs(self.ttl)
+  }
+}
+
+
+impl LimitsConfig {
+  pub(crate) fn ban_duration(&self) -> Result<TimeDelta> {
+    ban_duration(self.ban_duration)
+  }
+}
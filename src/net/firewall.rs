@@ -0,0 +1,194 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  convert::Infallible,
+  net::IpAddr,
+  task::{Context, Poll, Waker},
+};
+
+use libp2p::{
+  core::{transport::PortUse, Endpoint},
+  multiaddr::Protocol,
+  swarm::{
+    dummy,
+    behaviour::ConnectionClosed,
+    CloseConnection,
+    ConnectionDenied,
+    ConnectionId,
+    FromSwarm,
+    NetworkBehaviour,
+    THandler,
+    THandlerInEvent,
+    THandlerOutEvent,
+    ToSwarm,
+  },
+  Multiaddr,
+  PeerId,
+};
+
+use crate::net::ban_list::BanList;
+
+
+/// Denies connections of banned peers and IP addresses and limits the number of connections per IP address
+pub(crate) struct Firewall {
+  banned_peers: HashSet<PeerId>,
+  banned_ips: HashSet<IpAddr>,
+  max_connections_per_ip: u32,
+  connections: HashMap<ConnectionId, (PeerId, Option<IpAddr>)>,
+  close_connections: VecDeque<(PeerId, ConnectionId)>,
+  waker: Option<Waker>,
+}
+
+
+impl Firewall {
+  fn new(banned_peers: HashSet<PeerId>, banned_ips: HashSet<IpAddr>, max_connections_per_ip: u32) -> Self {
+    Self {
+      banned_peers,
+      banned_ips,
+      max_connections_per_ip,
+      connections: HashMap::default(),
+      close_connections: VecDeque::default(),
+      waker: None,
+    }
+  }
+
+
+  pub(crate) fn create(max_connections_per_ip: u32) -> Self {
+    Self::new(
+      HashSet::default(),
+      HashSet::default(),
+      max_connections_per_ip,
+    )
+  }
+
+
+  /// Replaces the bans and closes the open connections of the newly banned peers and addresses
+  pub(crate) fn set_bans(&mut self, ban_list: &BanList) {
+    self.banned_peers = ban_list.peers.keys().copied().collect();
+    self.banned_ips = ban_list.ips.keys().copied().collect();
+
+    for (connection_id, (peer_id, ip)) in &self.connections {
+      let banned_ip: bool = ip.is_some_and(|ip: IpAddr| self.banned_ips.contains(&ip));
+      if self.banned_peers.contains(peer_id) || banned_ip {
+        self.close_connections.push_back((*peer_id, *connection_id));
+      }
+    }
+
+    if let Some(waker) = self.waker.take() {
+      waker.wake();
+    }
+  }
+
+
+  fn check_ip(&self, addr: &Multiaddr) -> Result<(), ConnectionDenied> {
+    let ip: IpAddr = match remote_ip(addr) {
+      Some(ip) => ip,
+      None => return Ok(()),
+    };
+
+    if self.banned_ips.contains(&ip) {
+      return Err(ConnectionDenied::new(format!("The address {ip} is banned")));
+    }
+
+    let connections: usize = self.connections.values().filter(|(_, connection_ip)| *connection_ip == Some(ip)).count();
+    if connections >= self.max_connections_per_ip as usize {
+      return Err(ConnectionDenied::new(format!("Too many connections from {ip}")));
+    }
+    Ok(())
+  }
+
+
+  fn check_peer(&self, peer_id: &PeerId) -> Result<(), ConnectionDenied> {
+    if self.banned_peers.contains(peer_id) {
+      return Err(ConnectionDenied::new(format!("The peer {peer_id} is banned")));
+    }
+    Ok(())
+  }
+}
+
+
+impl NetworkBehaviour for Firewall {
+  type ConnectionHandler = dummy::ConnectionHandler;
+  type ToSwarm = Infallible;
+
+
+  fn handle_pending_inbound_connection(&mut self, _connection_id: ConnectionId, _local_addr: &Multiaddr, remote_addr: &Multiaddr) -> Result<(), ConnectionDenied> {
+    self.check_ip(remote_addr)
+  }
+
+
+  fn handle_established_inbound_connection(&mut self, connection_id: ConnectionId, peer: PeerId, _local_addr: &Multiaddr, remote_addr: &Multiaddr) -> Result<THandler<Self>, ConnectionDenied> {
+    self.check_peer(&peer)?;
+    self.connections.insert(connection_id, (peer, remote_ip(remote_addr)));
+    Ok(dummy::ConnectionHandler)
+  }
+
+
+  fn handle_pending_outbound_connection(&mut self, _connection_id: ConnectionId, maybe_peer: Option<PeerId>, _addresses: &[Multiaddr], _effective_role: Endpoint) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+    if let Some(peer_id) = maybe_peer {
+      self.check_peer(&peer_id)?;
+    }
+    Ok(Vec::new())
+  }
+
+
+  fn handle_established_outbound_connection(&mut self, connection_id: ConnectionId, peer: PeerId, addr: &Multiaddr, _role_override: Endpoint, _port_use: PortUse) -> Result<THandler<Self>, ConnectionDenied> {
+    self.check_peer(&peer)?;
+    if remote_ip(addr).is_some_and(|ip: IpAddr| self.banned_ips.contains(&ip)) {
+      return Err(ConnectionDenied::new(format!("The address {addr} is banned")));
+    }
+    self.connections.insert(connection_id, (peer, remote_ip(addr)));
+    Ok(dummy::ConnectionHandler)
+  }
+
+
+  fn on_swarm_event(&mut self, event: FromSwarm) {
+    if let FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) = event {
+      self.connections.remove(&connection_id);
+    }
+  }
+
+
+  fn on_connection_handler_event(&mut self, _peer_id: PeerId, _connection_id: ConnectionId, _event: THandlerOutEvent<Self>) {}
+
+
+  fn poll(&mut self, cx: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+    if let Some((peer_id, connection_id)) = self.close_connections.pop_front() {
+      return Poll::Ready(ToSwarm::CloseConnection {
+        peer_id,
+        connection: CloseConnection::One(connection_id),
+      });
+    }
+    self.waker = Some(cx.waker().clone());
+    Poll::Pending
+  }
+}
+
+
+/// The IP address of the remote end, relayed connections are attributed to no address
+fn remote_ip(addr: &Multiaddr) -> Option<IpAddr> {
+  if addr.iter().any(|protocol: Protocol| protocol == Protocol::P2pCircuit) {
+    return None;
+  }
+  match addr.iter().next()? {
+    Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+    Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+    _ => None,
+  }
+}
@@ -0,0 +1,201 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  collections::HashMap,
+  net::{SocketAddr, TcpListener},
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{extract::State, http::header::CONTENT_TYPE, response::IntoResponse, routing::get, Router};
+use libp2p::{
+  gossipsub::{MessageAcceptance, TopicHash},
+  metrics::{self, Recorder, Registry},
+  swarm::SwarmEvent,
+};
+use prometheus_client::{
+  encoding::{text::encode, EncodeLabelSet},
+  metrics::{counter::Counter, family::Family, gauge::Gauge},
+};
+use sha3::{Digest, Sha3_256};
+use tokio::task::{self, JoinHandle};
+use tracing::{info, warn};
+
+use crate::{
+  blockchain::{block::Block, chain, data::Data},
+  net::{
+    behaviour::BehaviourEvent,
+    validation::{topic_name, BLOCKS_DATA_TOPIC, BLOCKS_TOPIC},
+  },
+};
+
+
+/// Block data that wasn't seen in a block for this long is no longer counted in the mempool
+const MEMPOOL_TTL: Duration = Duration::from_secs(60 * 60);
+
+
+/// Swarm statistics of libp2p and the state of the chain as seen by this node, served over HTTP
+pub(crate) struct Metrics {
+  libp2p: metrics::Metrics,
+  peers: Gauge,
+  messages: Family<MessageLabels, Counter>,
+  chain_height: Gauge,
+  mempool_size: Gauge,
+  blocks: Counter,
+  /// Hashes of the accepted block data that is waiting to be mined
+  mempool: HashMap<Vec<u8>, Instant>,
+  server: JoinHandle<()>,
+}
+
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct MessageLabels {
+  topic: &'static str,
+  acceptance: &'static str,
+}
+
+
+impl Metrics {
+  /// Registers the metrics next to the bandwidth metrics of the transport and serves them on `addr`
+  pub(crate) fn serve(addr: SocketAddr, mut registry: Registry) -> Result<Self> {
+    let libp2p: metrics::Metrics = metrics::Metrics::new(&mut registry);
+
+    let sub_registry: &mut Registry = registry.sub_registry_with_prefix("system");
+    let peers: Gauge = Gauge::default();
+    sub_registry.register("peers", "Connected peers", peers.clone());
+    let messages: Family<MessageLabels, Counter> = Family::default();
+    sub_registry.register("gossip_messages", "Gossipsub messages by topic and validation result", messages.clone());
+    let chain_height: Gauge = Gauge::default();
+    sub_registry.register("chain_height", "Number of blocks in the longest chain seen", chain_height.clone());
+    let mempool_size: Gauge = Gauge::default();
+    sub_registry.register("mempool_size", "Block data waiting to be mined", mempool_size.clone());
+    let blocks: Counter = Counter::default();
+    sub_registry.register("blocks", "Valid blocks received, the rate is the mining rate", blocks.clone());
+
+    chain_height.set(i64::try_from(chain::height()?).unwrap_or(i64::MAX));
+
+    let listener: TcpListener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let router: Router = Router::new()
+    .route("/metrics", get(metrics_handler))
+    .with_state(Arc::new(registry));
+    let listener: tokio::net::TcpListener = tokio::net::TcpListener::from_std(listener)?;
+    info!(%addr, "Serving metrics");
+    let server: JoinHandle<()> = task::spawn(async move {
+      if let Err(error) = axum::serve(listener, router).await {
+        warn!(%error, "The metrics server stopped");
+      }
+    });
+
+    Ok(Self {
+      libp2p,
+      peers,
+      messages,
+      chain_height,
+      mempool_size,
+      blocks,
+      mempool: HashMap::new(),
+      server,
+    })
+  }
+
+
+  pub(crate) fn record_event(&mut self, event: &SwarmEvent<BehaviourEvent>) {
+    match event {
+      SwarmEvent::Behaviour(BehaviourEvent::Identify(event)) => self.libp2p.record(event),
+      SwarmEvent::Behaviour(BehaviourEvent::Kademlia(event)) => self.libp2p.record(event),
+      SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => self.libp2p.record(event),
+      SwarmEvent::Behaviour(BehaviourEvent::Relay(event)) => self.libp2p.record(event),
+      SwarmEvent::Behaviour(BehaviourEvent::Dcutr(event)) => self.libp2p.record(event),
+      SwarmEvent::Behaviour(_) => (),
+      event => {
+        match event {
+          SwarmEvent::ConnectionEstablished { num_established, .. } if num_established.get() == 1 => {
+            self.peers.inc();
+          },
+          SwarmEvent::ConnectionClosed { num_established: 0, .. } => {
+            self.peers.dec();
+          },
+          _ => (),
+        }
+        self.libp2p.record(event);
+      },
+    }
+  }
+
+
+  /// Counts the message and follows the block data from the mempool into blocks
+  pub(crate) fn record_message(&mut self, topic: &TopicHash, data: &[u8], acceptance: &MessageAcceptance) {
+    let labels: MessageLabels = MessageLabels {
+      topic: topic_name(topic),
+      acceptance: match acceptance {
+        MessageAcceptance::Accept => "accept",
+        MessageAcceptance::Reject => "reject",
+        MessageAcceptance::Ignore => "ignore",
+      },
+    };
+    self.messages.get_or_create(&labels).inc();
+
+    if !matches!(acceptance, MessageAcceptance::Accept) {
+      return;
+    }
+    if labels.topic == BLOCKS_DATA_TOPIC {
+      if let Ok(data) = serde_json::from_slice::<Data>(data) {
+        self.mempool.insert(data_hash(&data), Instant::now());
+      }
+    } else if labels.topic == BLOCKS_TOPIC {
+      if let Ok(block) = serde_json::from_slice::<Block>(data) {
+        self.blocks.inc();
+        self.mempool.remove(&data_hash(&block.get_signed_data()));
+        let height: i64 = i64::try_from(block.get_id() + 1).unwrap_or(i64::MAX);
+        self.chain_height.set(self.chain_height.get().max(height));
+      }
+    }
+    self.mempool_size.set(self.mempool.len() as i64);
+  }
+
+
+  pub(crate) fn prune_mempool(&mut self) {
+    self.mempool.retain(|_, received: &mut Instant| received.elapsed() < MEMPOOL_TTL);
+    self.mempool_size.set(self.mempool.len() as i64);
+  }
+}
+
+
+impl Drop for Metrics {
+  /// A restarted node binds the address again
+  fn drop(&mut self) {
+    self.server.abort();
+  }
+}
+
+
+async fn metrics_handler(State(registry): State<Arc<Registry>>) -> impl IntoResponse {
+  let mut body: String = String::new();
+  if let Err(error) = encode(&mut body, &registry) {
+    warn!(%error, "Failed to encode the metrics");
+  }
+  ([(CONTENT_TYPE, "application/openmetrics-text; version=1.0.0; charset=utf-8")], body)
+}
+
+
+fn data_hash(data: &Data) -> Vec<u8> {
+  Sha3_256::digest(serde_json::to_vec(data).unwrap_or_default()).to_vec()
+}
+
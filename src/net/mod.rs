@@ -16,31 +16,46 @@
 
 
 pub(crate) mod server;
+pub(crate) mod ban_list;
 mod behaviour;
+mod bootstrap;
+mod firewall;
+mod nat;
+pub(crate) mod peer_cache;
+mod protocol;
+mod record_store;
+mod metrics;
+mod request;
+pub(crate) mod role;
 mod server_list;
 mod send_data;
+mod validation;
 pub(crate) mod api;
+pub(crate) mod config;
 
 
 use std::{
-  net::{Ipv4Addr, Ipv6Addr},
+  error::Error,
   time::Duration,
-  env::args,
-  collections::HashSet,
+  collections::{HashMap, HashSet},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use api::API;
 use ssh_key::PrivateKey;
 use tokio::{
   task::{self, JoinHandle},
-  sync::watch::{Sender, Receiver, channel},
-  time::{Interval, interval},
+  sync::{
+    mpsc::{UnboundedSender, UnboundedReceiver, unbounded_channel},
+  },
+  time::{Instant, Interval, interval, interval_at},
 };
+use tracing::{debug, info, trace, warn};
 use libp2p::{
   futures::StreamExt,
-  gossipsub::{Topic, Sha256Topic, Message},
+  gossipsub::{Topic, TopicHash, Sha256Topic, Message, MessageAcceptance},
   identity::Keypair,
+  metrics::Registry,
   multiaddr::Protocol,
   noise,
   swarm::{Config, Swarm, SwarmEvent},
@@ -48,115 +63,474 @@ use libp2p::{
   tls,
   yamux,
   Multiaddr,
+  PeerId,
   SwarmBuilder,
-  kad,
+  kad::{self, store::RecordStore},
   gossipsub,
   identify,
+  mdns,
+  relay,
+  autonat,
+  dcutr,
+  TransportError,
 };
 
-use crate::net::{
-  behaviour::{Behaviour, BehaviourEvent},
-  server_list::ServerList,
-  send_data::SendData,
+use crate::{
+  net::{
+    ban_list::{BanList, BanTarget},
+    behaviour::{Behaviour, BehaviourEvent},
+    bootstrap::Bootstrap,
+    config::NetConfig,
+    metrics::Metrics,
+    nat::Nat,
+    peer_cache::PeerCache,
+    protocol::{identify_protocol, is_compatible},
+    request::{Request, RecordSender, FlushSender},
+    role::Role,
+    send_data::SendData,
+    server_list::{ServerList, SERVER_TTL},
+    validation::{block_records, topic_name, validate_message, validate_record, BLOCKS_DATA_TOPIC, BLOCKS_TOPIC, BLOCKCHAIN_TOPIC},
+  },
+  blockchain::{block::Block, chain::{self, Appended}, data::Data},
+  explorer::Explorer,
 };
 
 
+/// How often the peer cache, the server list, the ban list and the record store are synchronized with the disk
+pub(crate) const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(30);
+
+
+/// The node core shared by every role, the role decides which behaviours take part
 pub(crate) struct Net {
   swarm: Swarm<Behaviour>,
-  command_receiver: Receiver<SendData>,
+  role: Role,
+  command_receiver: UnboundedReceiver<SendData>,
+  /// Messages waiting for gossipsub peers
+  pending: HashSet<SendData>,
+  flush_senders: Vec<FlushSender>,
+  request_receiver: UnboundedReceiver<Request>,
+  config: NetConfig,
+  peer_cache: PeerCache,
+  nat: Nat,
+  ban_list: BanList,
+  /// Only servers announce themselves in the server list
+  server_list: Option<ServerList>,
+  record_queries: HashMap<kad::QueryId, RecordSender>,
+  metrics: Option<Metrics>,
+  explorer: Option<Explorer>,
 }
 
 
 impl Net {
-  fn new(swarm: Swarm<Behaviour>, command_receiver: Receiver<SendData>) -> Self {
-    Self {
-      swarm,
-      command_receiver,
+  /// A ban list that can't be saved must not stop the node, the ban still holds until the restart
+  fn record_offence(&mut self, peer_id: PeerId) {
+    if let Err(error) = self.try_record_offence(peer_id) {
+      warn!(%peer_id, %error, "Failed to record the offence of the peer");
+    }
+  }
+
+
+  fn try_record_offence(&mut self, peer_id: PeerId) -> Result<()> {
+    let Some(ban) = self.ban_list.record_offence(peer_id, self.config.limits.max_invalid_messages, self.config.limits.ban_duration()?)? else {
+      return Ok(());
+    };
+    warn!(%peer_id, "Banned the peer for repeatedly sending invalid data");
+    let saved: Result<()> = self.ban_list.add_and_save(BanTarget::Peer(peer_id), ban);
+    self.swarm.behaviour_mut().firewall.set_bans(&self.ban_list);
+    saved
+  }
+
+
+  fn listen_on_relay(&mut self, addr: Multiaddr) {
+    if let Err(error) = self.swarm.listen_on(addr.clone()) {
+      warn!(%addr, error = %transport_error(error), "Failed to reserve a slot on the relay");
+      self.nat.relay_closed(&addr);
+    }
+  }
+
+
+  fn add_server_addr(&mut self, addr: Multiaddr) -> Result<()> {
+    if let Some(server_list) = &mut self.server_list {
+      server_list.add_addr(*self.swarm.local_peer_id(), addr);
+      server_list.save_default()?;
+    }
+    Ok(())
+  }
+
+
+  fn remove_server_addrs(&mut self, addrs: &[Multiaddr]) -> Result<()> {
+    if let Some(server_list) = &mut self.server_list {
+      addrs.iter().for_each(|addr: &Multiaddr| server_list.remove_addr(self.swarm.local_peer_id(), addr));
+      server_list.save_default()?;
+    }
+    Ok(())
+  }
+
+
+  /// Full nodes store blocks that extend their chain and reject the ones their ledger doesn't accept,
+  /// servers make the users registered by an accepted block resolvable through the DHT
+  fn process_message(&mut self, topic: &TopicHash, data: &[u8]) -> MessageAcceptance {
+    debug!(%topic, size = data.len(), "Received a message");
+    if self.role.stores_chain() && topic_name(topic) == BLOCKS_TOPIC && !self.store_block(data) {
+      return MessageAcceptance::Reject;
+    }
+    if let (Some(explorer), BLOCKS_DATA_TOPIC) = (&self.explorer, topic_name(topic)) {
+      if let Ok(data) = serde_json::from_slice::<Data>(data) {
+        explorer.data_received(&data);
+      }
+    }
+    if !self.role.is_server() {
+      return MessageAcceptance::Accept;
+    }
+
+    for record in block_records(topic, data) {
+      self.swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One).ok();
+    }
+    MessageAcceptance::Accept
+  }
+
+
+  /// Subscribers of the explorer hear about the stored blocks and the ones competing with the local chain,
+  /// `false` when the ledger rejects the block, a transfer of money the signer doesn't have for example
+  fn store_block(&self, data: &[u8]) -> bool {
+    let Ok(block) = serde_json::from_slice::<Block>(data) else {
+      return true;
+    };
+
+    match chain::append(&block) {
+      Ok(Appended::Stored) => {
+        info!(block_id = block.get_id(), "Stored the block");
+        if let Some(explorer) = &self.explorer {
+          explorer.block_stored(&block);
+        }
+      },
+      Ok(Appended::Skipped) => match chain::block_by_id(block.get_id()) {
+        Ok(Some(stored)) if stored.get_hash() != block.get_hash() => {
+          info!(block_id = block.get_id(), "Received a block competing with the local chain");
+          if let Some(explorer) = &self.explorer {
+            explorer.block_competing(&stored, &block);
+          }
+        },
+        _ => debug!(block_id = block.get_id(), "The block doesn't extend the local chain"),
+      },
+      Ok(Appended::Rejected) => {
+        info!(block_id = block.get_id(), "The ledger rejects the block");
+        return false;
+      },
+      Err(error) => warn!(%error, "Failed to store the block"),
+    }
+    true
+  }
+
+
+  fn process_request(&mut self, request: Request) {
+    match request {
+      Request::PutRecord(record) => {
+        if let Err(error) = self.swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One) {
+          warn!(%error, "Failed to store a record");
+        }
+      },
+
+      Request::GetRecord(key, sender) => {
+        let query_id: kad::QueryId = self.swarm.behaviour_mut().kademlia.get_record(key);
+        self.record_queries.insert(query_id, sender);
+      },
+
+      Request::ListPeers(sender) => {
+        sender.send(self.swarm.connected_peers().copied().collect()).ok();
+      },
+
+      Request::Flush(sender) => {
+        // The messages may still wait in the command channel
+        while let Ok(data) = self.command_receiver.try_recv() {
+          self.pending.insert(data);
+        }
+        self.flush_senders.push(sender);
+      },
+    }
+  }
+
+
+  fn publish_pending(&mut self) {
+    for data in self.pending.clone() {
+      if self.swarm.behaviour_mut().gossipsub.publish(data.topic(), data.data()).is_ok() {
+        self.pending.remove(&data);
+      }
+    }
+    if self.pending.is_empty() {
+      self.flush_senders.drain(..).for_each(|sender: FlushSender| {
+        sender.send(()).ok();
+      });
+    }
+  }
+
+
+  /// Answers the pending lookup with the first valid record, invalid ones are skipped until the query ends
+  fn process_record(&mut self, query_id: kad::QueryId, result: Result<kad::GetRecordOk, kad::GetRecordError>, last: bool) {
+    let Some(sender) = self.record_queries.remove(&query_id) else {
+      return;
+    };
+
+    match result {
+      Ok(kad::GetRecordOk::FoundRecord(kad::PeerRecord { record, .. })) if validate_record(&record) => {
+        sender.send(Some(record.value)).ok();
+        if let Some(mut query) = self.swarm.behaviour_mut().kademlia.query_mut(&query_id) {
+          query.finish();
+        }
+      },
+      _ if last => {
+        sender.send(None).ok();
+      },
+      _ => {
+        self.record_queries.insert(query_id, sender);
+      },
+    }
+  }
+
+
+  /// Every step is tried, a file that can't be read or written must not stop the node or the other steps
+  fn maintain(&mut self) {
+    if self.config.bootstrap.peer_cache_size > 0 {
+      if let Err(error) = self.peer_cache.save_if_changed(self.config.bootstrap.peer_cache_size) {
+        warn!(%error, "Failed to save the peer cache");
+      }
+    }
+    let local_peer_id: PeerId = *self.swarm.local_peer_id();
+    if let Some(server_list) = &mut self.server_list {
+      server_list.touch(&local_peer_id);
+      if let Err(error) = server_list.save_default() {
+        warn!(%error, "Failed to save the server list");
+      }
+    }
+    if let Err(error) = self.swarm.behaviour_mut().kademlia.store_mut().save_if_changed() {
+      warn!(%error, "Failed to save the DHT records");
+    }
+    match self.ban_list.reload() {
+      Ok(()) => self.swarm.behaviour_mut().firewall.set_bans(&self.ban_list),
+      Err(error) => warn!(%error, "Failed to reload the ban list"),
+    }
+    if let Some(metrics) = &mut self.metrics {
+      metrics.prune_mempool();
     }
   }
 
 
-  pub(crate) fn start(mut self) -> JoinHandle<Result<()>> {
-    let mut tasks: HashSet<SendData> = HashSet::new();
+  pub(crate) fn start(self) -> JoinHandle<Result<()>> {
+    task::spawn(self.run())
+  }
+
+
+  pub(crate) async fn run(mut self) -> Result<()> {
     let mut interval: Interval = interval(Duration::from_secs(1));
-    task::spawn(async move {
-      loop {
-        tokio::select! {
-          event = self.swarm.select_next_some() => match event {
+    let mut maintenance_interval: Interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+    let bootstrap_period: Duration = self.config.timing.bootstrap_interval();
+    let mut bootstrap_interval: Interval = interval_at(Instant::now() + bootstrap_period, bootstrap_period);
+    loop {
+      tokio::select! {
+        event = self.swarm.select_next_some() => {
+          if let Some(metrics) = &mut self.metrics {
+            metrics.record_event(&event);
+          }
+          match event {
             SwarmEvent::Behaviour(event) => {
               match event {
                 BehaviourEvent::Identify(event) => match event {
-                  identify::Event::Received { peer_id, info: identify::Info { listen_addrs, .. }, .. } => {
-                    listen_addrs.iter().for_each(|addr: &Multiaddr| {
+                  identify::Event::Received { peer_id, info, .. } if !is_compatible(&identify_protocol(&self.config.chain_id), &info.protocol_version) => {
+                    debug!(%peer_id, protocol_version = %info.protocol_version, "Disconnecting a peer of another chain or protocol version");
+                    self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                    self.swarm.disconnect_peer_id(peer_id).ok();
+                  },
+
+                  identify::Event::Received { peer_id, info, .. } => {
+                    trace!(%peer_id, agent_version = %info.agent_version, "Identified the peer");
+                    info.listen_addrs.iter().for_each(|addr: &Multiaddr| {
                       self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
                     });
+                    for addr in self.nat.add_relay(peer_id, &info) {
+                      self.listen_on_relay(addr);
+                    }
+                    self.peer_cache.add_addrs(peer_id, info.listen_addrs);
                   },
-      
-                  identify::Event::Sent { .. } => (),
-                  identify::Event::Pushed { .. } => (),
-                  identify::Event::Error { .. } => (),
+
+                  identify::Event::Sent { peer_id, .. } => trace!(%peer_id, "Sent the identify info"),
+                  identify::Event::Pushed { peer_id, .. } => trace!(%peer_id, "Pushed the identify info"),
+                  identify::Event::Error { peer_id, error, .. } => debug!(%peer_id, %error, "Identify failed"),
                 },
-    
+
                 BehaviourEvent::Kademlia(event) => match event {
-                  kad::Event::InboundRequest { .. } => (),
-                  kad::Event::OutboundQueryProgressed { .. } => (),
-                  kad::Event::RoutingUpdated { .. } => (),
-                  kad::Event::UnroutablePeer { .. } => (),
-                  kad::Event::RoutablePeer { .. } => (),
-                  kad::Event::PendingRoutablePeer { .. } => (),
-                  kad::Event::ModeChanged { .. } => (),
+                  kad::Event::InboundRequest { request: kad::InboundRequest::PutRecord { source, record: Some(record), .. } } => {
+                    if validate_record(&record) {
+                      trace!(%source, "Storing a DHT record");
+                      self.swarm.behaviour_mut().kademlia.store_mut().put(record).ok();
+                    } else {
+                      debug!(%source, "Rejected an invalid DHT record");
+                      self.record_offence(source);
+                    }
+                  },
+                  kad::Event::InboundRequest { request } => trace!(?request, "Inbound DHT request"),
+                  kad::Event::OutboundQueryProgressed { id, result: kad::QueryResult::GetRecord(result), step, .. } => {
+                    self.process_record(id, result, step.last);
+                  },
+                  kad::Event::OutboundQueryProgressed { result, .. } => trace!(?result, "DHT query progressed"),
+                  kad::Event::RoutingUpdated { peer, addresses, is_new_peer, .. } => {
+                    trace!(%peer, is_new_peer, "Routing table updated");
+                    self.peer_cache.add_addrs(peer, addresses.into_vec());
+                  },
+                  kad::Event::UnroutablePeer { peer } => trace!(%peer, "The peer has no known address"),
+                  kad::Event::RoutablePeer { peer, address } => trace!(%peer, %address, "The peer can be added to the routing table"),
+                  kad::Event::PendingRoutablePeer { peer, address } => trace!(%peer, %address, "The peer waits for a place in the routing table"),
+                  kad::Event::ModeChanged { new_mode } => debug!(mode = %new_mode, "Kademlia mode changed"),
                 },
-    
+
                 BehaviourEvent::Gossipsub(event) => match event {
-                  gossipsub::Event::Message { message: Message { data, .. } , .. } => println!("{data:?}"),
-                  gossipsub::Event::Subscribed { .. } => (),
-                  gossipsub::Event::Unsubscribed { .. } => (),
-                  gossipsub::Event::GossipsubNotSupported { .. } => (),
+                  gossipsub::Event::Message { propagation_source, message_id, message: Message { data, topic, .. } } => {
+                    let mut acceptance: MessageAcceptance = validate_message(&topic, &data);
+                    if matches!(acceptance, MessageAcceptance::Accept) {
+                      acceptance = self.process_message(&topic, &data);
+                    }
+                    if let Some(metrics) = &mut self.metrics {
+                      metrics.record_message(&topic, &data, &acceptance);
+                    }
+                    match acceptance {
+                      MessageAcceptance::Accept => (),
+                      MessageAcceptance::Reject => {
+                        debug!(%propagation_source, %topic, "Rejected an invalid message");
+                        self.record_offence(propagation_source);
+                      },
+                      MessageAcceptance::Ignore => trace!(%propagation_source, %topic, "Ignored a message"),
+                    }
+                    self.swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &propagation_source, acceptance)?;
+                  },
+                  gossipsub::Event::Subscribed { peer_id, topic } => debug!(%peer_id, %topic, "The peer subscribed"),
+                  gossipsub::Event::Unsubscribed { peer_id, topic } => debug!(%peer_id, %topic, "The peer unsubscribed"),
+                  gossipsub::Event::GossipsubNotSupported { peer_id } => debug!(%peer_id, "The peer doesn't support gossipsub"),
+                },
+
+                BehaviourEvent::Mdns(event) => match event {
+                  mdns::Event::Discovered(peers) => {
+                    peers.into_iter().for_each(|(peer_id, addr): (PeerId, Multiaddr)| {
+                      debug!(%peer_id, %addr, "Discovered the peer over mDNS");
+                      self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
+                    });
+                  },
+
+                  mdns::Event::Expired(peers) => {
+                    peers.iter().for_each(|(peer_id, addr): &(PeerId, Multiaddr)| {
+                      debug!(%peer_id, %addr, "The mDNS record of the peer expired");
+                      self.swarm.behaviour_mut().kademlia.remove_address(peer_id, addr);
+                    });
+                  },
+                },
+
+                BehaviourEvent::Relay(event) => debug!(?event, "Relay server event"),
+
+                BehaviourEvent::RelayClient(event) => match event {
+                  relay::client::Event::ReservationReqAccepted { relay_peer_id, renewal, .. } => {
+                    info!(%relay_peer_id, renewal, "Reserved a slot on the relay");
+                  },
+                  relay::client::Event::OutboundCircuitEstablished { relay_peer_id, .. } => {
+                    debug!(%relay_peer_id, "Connected to a peer through the relay");
+                  },
+                  relay::client::Event::InboundCircuitEstablished { src_peer_id, .. } => {
+                    debug!(%src_peer_id, "A peer connected through the relay");
+                  },
+                },
+
+                BehaviourEvent::Autonat(event) => match event {
+                  autonat::Event::StatusChanged { old, new } => {
+                    info!(?old, ?new, "NAT status changed");
+                    for addr in self.nat.set_status(&new) {
+                      self.listen_on_relay(addr);
+                    }
+                  },
+
+                  autonat::Event::InboundProbe(event) => trace!(?event, "Inbound AutoNAT probe"),
+                  autonat::Event::OutboundProbe(event) => trace!(?event, "Outbound AutoNAT probe"),
+                },
+
+                BehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result }) => match result {
+                  Ok(_) => debug!(%remote_peer_id, "Upgraded the relayed connection with hole punching"),
+                  Err(error) => debug!(%remote_peer_id, %error, "Hole punching failed"),
                 },
+
+                BehaviourEvent::Firewall(event) => match event {},
+                BehaviourEvent::Limits(event) => match event {},
               }
             },
-    
-            SwarmEvent::ConnectionEstablished { .. } => (),
-            SwarmEvent::ConnectionClosed { .. } => (),
-            SwarmEvent::IncomingConnection { .. } => (),
-            SwarmEvent::IncomingConnectionError { .. } => (),
-            SwarmEvent::OutgoingConnectionError { .. } => (),
-            SwarmEvent::NewListenAddr { .. } => (),
-            SwarmEvent::ExpiredListenAddr { .. } => (),
-            SwarmEvent::ListenerClosed { .. } => (),
-            SwarmEvent::ListenerError { .. } => (),
-            SwarmEvent::Dialing { .. } => (),
-            SwarmEvent::NewExternalAddrCandidate { .. } => (),
-            SwarmEvent::ExternalAddrConfirmed { .. } => (),
-            SwarmEvent::ExternalAddrExpired { .. } => (),
-            SwarmEvent::NewExternalAddrOfPeer { .. } => (),
-    
-            _ => (),
-          },
 
-          _ = interval.tick() => {
-            for data in tasks.clone() {
-              if self.swarm.behaviour_mut().gossipsub.publish(data.topic(), data.data()).is_ok() {
-                tasks.remove(&data);
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
+              debug!(%peer_id, addr = %endpoint.get_remote_address(), num_established, "Connection established");
+            },
+
+            SwarmEvent::ConnectionClosed { peer_id, cause, .. } => debug!(%peer_id, ?cause, "Connection closed"),
+            SwarmEvent::IncomingConnection { send_back_addr, .. } => trace!(%send_back_addr, "Incoming connection"),
+
+            SwarmEvent::IncomingConnectionError { send_back_addr, error, .. } => {
+              debug!(%send_back_addr, %error, "Incoming connection failed");
+            },
+
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => debug!(?peer_id, %error, "Dial failed"),
+
+            SwarmEvent::NewListenAddr { address, .. } => {
+              match self.role.is_server() {
+                true => info!(%address, "Listening"),
+                false => debug!(%address, "Listening"),
               }
-            }
-          },
+              self.add_server_addr(address)?;
+            },
 
-          Ok(_) = self.command_receiver.changed() => {
-            let data: SendData = self.command_receiver.borrow_and_update().clone();
-            tasks.insert(data);
-          },
-        }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+              debug!(%address, "Stopped listening");
+              self.remove_server_addrs(&[address])?;
+            },
+
+            SwarmEvent::ListenerClosed { addresses, reason, .. } => {
+              debug!(?addresses, ?reason, "Listener closed");
+              addresses.iter().for_each(|addr: &Multiaddr| self.nat.relay_closed(addr));
+              self.remove_server_addrs(&addresses)?;
+            },
+
+            SwarmEvent::ListenerError { error, .. } => warn!(%error, "Listener failed"),
+            SwarmEvent::Dialing { peer_id, .. } => trace!(?peer_id, "Dialing"),
+            SwarmEvent::NewExternalAddrCandidate { address } => trace!(%address, "New external address candidate"),
+
+            SwarmEvent::ExternalAddrConfirmed { address } => {
+              info!(%address, "External address confirmed");
+              self.add_server_addr(address)?;
+            },
+
+            SwarmEvent::ExternalAddrExpired { address } => {
+              debug!(%address, "External address expired");
+              self.remove_server_addrs(&[address])?;
+            },
+
+            SwarmEvent::NewExternalAddrOfPeer { peer_id, address } => trace!(%peer_id, %address, "New external address of the peer"),
+
+            _ => (),
+          }
+        },
+
+        _ = interval.tick() => self.publish_pending(),
+
+        _ = bootstrap_interval.tick() => self.swarm.behaviour_mut().refresh_routing_table(),
+
+        _ = maintenance_interval.tick() => self.maintain(),
+
+        Some(data) = self.command_receiver.recv() => {
+          self.pending.insert(data);
+        },
+
+        Some(request) = self.request_receiver.recv() => self.process_request(request),
       }
-    })
+    }
   }
 
 
-  pub(crate) fn from_key(key: &PrivateKey) -> Result<API> {
-    let key_bytes: [u8; 32] = key.key_data().ed25519().context("The key was not generated using the ed25519 algorithm")?.private.to_bytes();
-    let key: Keypair = Keypair::ed25519_from_bytes(key_bytes)?;
-
-    let behaviour: Behaviour = Behaviour::from_key(key.clone())?;
+  pub(crate) fn from_key(key: &PrivateKey, config: &NetConfig, role: Role, command_receiver: UnboundedReceiver<SendData>, request_receiver: UnboundedReceiver<Request>) -> Result<Self> {
+    let key: Keypair = to_keypair(key)?;
+    // Checked at the start, the first ban is too late to find a bad config
+    config.limits.ban_duration()?;
+    let mut registry: Registry = Registry::default();
 
     let mut swarm: Swarm<Behaviour> = SwarmBuilder::with_existing_identity(key.clone())
     .with_tokio()
@@ -167,60 +541,115 @@ impl Net {
     )?
     .with_quic()
     .with_dns()?
-    .with_behaviour(|_| -> Behaviour {
-      behaviour
+    .with_relay_client(
+      (tls::Config::new, noise::Config::new),
+      yamux::Config::default,
+    )?
+    .with_bandwidth_metrics(&mut registry)
+    .with_behaviour(|key: &Keypair, relay_client: relay::client::Behaviour| -> Result<Behaviour, Box<dyn Error + Send + Sync>> {
+      Ok(Behaviour::from_key(key.clone(), config, relay_client, role)?)
     })?
-    .with_swarm_config(|config: Config| -> Config {
-      config.with_idle_connection_timeout(Duration::from_secs(u64::MAX))
+    .with_swarm_config(|swarm_config: Config| -> Config {
+      swarm_config.with_idle_connection_timeout(config.timing.idle_connection_timeout())
     })
     .build();
 
-    let addr_v4_tcp = Multiaddr::empty()
-    .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-    .with(Protocol::Tcp(0));
-
-    let addr_v6_tcp = Multiaddr::empty()
-    .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-    .with(Protocol::Tcp(0));
-    
-    let addr_v4_udp = Multiaddr::empty()
-    .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-    .with(Protocol::Udp(0))
-    .with(Protocol::QuicV1);
-
-    let addr_v6_udp = Multiaddr::empty()
-    .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-    .with(Protocol::Udp(0))
-    .with(Protocol::QuicV1);
-    
-    swarm.listen_on(addr_v4_tcp)?;
-    swarm.listen_on(addr_v6_tcp)?;
-    swarm.listen_on(addr_v4_udp)?;
-    swarm.listen_on(addr_v6_udp)?;
-
-    if let Ok(server_list) = ServerList::from_default_path() {
-      for (peer_id, addresses) in server_list.addresses.iter() {
-        addresses.values().for_each(|addr: &Multiaddr| {
-          swarm.behaviour_mut().kademlia.add_address(peer_id, addr.clone());
-        });
+    for addr in config.listen_addrs()? {
+      if let Err(error) = swarm.listen_on(addr.clone()) {
+        bail!("Failed to listen on {addr}: {}", transport_error(error));
       }
     }
-    else {
-      let server_id: String = args().nth(1).context("Server ID isn't provided")?;
-      let server_ip: String = args().nth(2).context("Server IP isn't provided")?;
-      swarm.behaviour_mut().kademlia.add_address(&server_id.parse()?, server_ip.parse()?);
+    for addr in &config.external_addrs {
+      swarm.add_external_address(addr.clone());
+    }
+
+    // The first servers of a network have nobody to join, a client without peers is useless
+    let bootstrap: Bootstrap = Bootstrap::from_config(&config.bootstrap)?;
+    if role == Role::Client && bootstrap.is_empty() && !config.mdns {
+      bail!("No bootstrap peers, add them to the config, pass --bootstrap or enable --mdns");
+    }
+    if role.is_server() {
+      swarm.behaviour_mut().kademlia.set_mode(Some(kad::Mode::Server));
+    }
+    bootstrap.start(&mut swarm)?;
+
+    if role.is_gossiping() {
+      let blocks_data_topic: Topic<_> = Sha256Topic::new(BLOCKS_DATA_TOPIC);
+      let blocks_topic: Topic<_> = Sha256Topic::new(BLOCKS_TOPIC);
+      let blockchain_topic: Topic<_> = Sha256Topic::new(BLOCKCHAIN_TOPIC);
+      swarm.behaviour_mut().gossipsub.subscribe(&blocks_data_topic)?;
+      swarm.behaviour_mut().gossipsub.subscribe(&blocks_topic)?;
+      swarm.behaviour_mut().gossipsub.subscribe(&blockchain_topic)?;
     }
-    swarm.behaviour_mut().kademlia.bootstrap()?;
 
-    let blocks_data_topic: Topic<_> = Sha256Topic::new("blocks_data");
-    let blocks_topic: Topic<_> = Sha256Topic::new("blocks");
-    let blockchain_topic: Topic<_> = Sha256Topic::new("blockchain");
-    swarm.behaviour_mut().gossipsub.subscribe(&blocks_data_topic)?;
-    swarm.behaviour_mut().gossipsub.subscribe(&blocks_topic)?;
-    swarm.behaviour_mut().gossipsub.subscribe(&blockchain_topic)?;
+    let server_list: Option<ServerList> = match role.is_server() {
+      true => {
+        let mut server_list: ServerList = ServerList::from_default_path().unwrap_or_default();
+        server_list.prune(SERVER_TTL);
+        server_list.remove_peer(swarm.local_peer_id());
+        server_list.save_default()?;
+        Some(server_list)
+      },
+      false => None,
+    };
+
+    let metrics: Option<Metrics> = match (role.is_server(), config.metrics) {
+      (true, Some(addr)) => Some(Metrics::serve(addr, registry)?),
+      _ => None,
+    };
+    let explorer: Option<Explorer> = match (role.stores_chain(), config.explorer) {
+      (true, Some(addr)) => Some(Explorer::serve(addr)?),
+      _ => None,
+    };
+
+    Ok(Self {
+      swarm,
+      role,
+      command_receiver,
+      pending: HashSet::new(),
+      flush_senders: Vec::new(),
+      request_receiver,
+      config: config.clone(),
+      peer_cache: PeerCache::from_default_path()?,
+      nat: Nat::from_config(&config.nat),
+      ban_list: BanList::from_default_path()?,
+      server_list,
+      record_queries: HashMap::new(),
+      metrics,
+      explorer,
+    })
+  }
+
+
+  /// Starts the network of the interactive client in the background
+  pub(crate) fn start_client(key: &PrivateKey, config: &NetConfig) -> Result<API> {
+    let (sender, receiver): (UnboundedSender<SendData>, UnboundedReceiver<SendData>) = unbounded_channel();
+    let (request_sender, request_receiver): (UnboundedSender<Request>, UnboundedReceiver<Request>) = unbounded_channel();
+    let net: Self = Self::from_key(key, config, Role::Client, receiver, request_receiver)?;
+    Ok(API::new(net.start(), sender, request_sender))
+  }
+}
+
+
+pub(crate) fn to_keypair(key: &PrivateKey) -> Result<Keypair> {
+  let key_bytes: [u8; 32] = key.key_data().ed25519().context("The key was not generated using the ed25519 algorithm")?.private.to_bytes();
+  Ok(Keypair::ed25519_from_bytes(key_bytes)?)
+}
+
+
+pub(crate) fn split_peer_addr(addr: &Multiaddr) -> Result<(PeerId, Multiaddr)> {
+  let mut peer_addr: Multiaddr = addr.clone();
+  match peer_addr.pop() {
+    Some(Protocol::P2p(peer_id)) => Ok((peer_id, peer_addr)),
+    _ => bail!("The address {addr} doesn't end with /p2p/<PEER_ID>"),
+  }
+}
+
 
-    let (sender, receiver): (Sender<SendData>, Receiver<SendData>) = channel(SendData::default());
-    let net: Self = Self::new(swarm, receiver);
-    Ok(API::new(net.start(), sender))
+/// `TransportError::Other` displays nothing, the cause is the inner error
+fn transport_error(error: TransportError<std::io::Error>) -> String {
+  match error {
+    TransportError::Other(error) => error.to_string(),
+    error => error.to_string(),
   }
 }
@@ -0,0 +1,109 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::collections::{HashMap, HashSet};
+
+use libp2p::{
+  autonat::NatStatus,
+  identify,
+  multiaddr::Protocol,
+  relay,
+  Multiaddr,
+  PeerId,
+};
+
+use crate::net::{config::NatConfig, server_list::is_routable};
+
+
+/// Keeps relay reservations of a client while AutoNAT reports it as unreachable
+pub(crate) struct Nat {
+  config: NatConfig,
+  private: bool,
+  relays: HashMap<PeerId, Multiaddr>,
+  reserved: HashSet<PeerId>,
+}
+
+
+impl Nat {
+  fn new(config: NatConfig, private: bool, relays: HashMap<PeerId, Multiaddr>, reserved: HashSet<PeerId>) -> Self {
+    Self {
+      config,
+      private,
+      relays,
+      reserved,
+    }
+  }
+
+
+  pub(crate) fn from_config(config: &NatConfig) -> Self {
+    Self::new(
+      config.clone(),
+      false,
+      HashMap::default(),
+      HashSet::default(),
+    )
+  }
+
+
+  /// Remembers the peer if it offers a circuit relay, returns the relayed addresses to listen on if reservations are needed now
+  pub(crate) fn add_relay(&mut self, peer_id: PeerId, info: &identify::Info) -> Vec<Multiaddr> {
+    if !self.config.hole_punching || !info.protocols.contains(&relay::HOP_PROTOCOL_NAME) {
+      return Vec::new();
+    }
+    if let Some(addr) = info.listen_addrs.iter().find(|addr: &&Multiaddr| is_routable(addr)) {
+      let circuit_addr: Multiaddr = addr.clone().with(Protocol::P2p(peer_id)).with(Protocol::P2pCircuit);
+      self.relays.insert(peer_id, circuit_addr);
+    }
+    self.reservations()
+  }
+
+
+  /// Returns the relayed addresses to listen on after the reachability of the node changed
+  pub(crate) fn set_status(&mut self, status: &NatStatus) -> Vec<Multiaddr> {
+    self.private = matches!(status, NatStatus::Private);
+    self.reservations()
+  }
+
+
+  /// Forgets the reservation so that the relay can be used again
+  pub(crate) fn relay_closed(&mut self, addr: &Multiaddr) {
+    let relay: Option<PeerId> = addr.iter().take_while(|protocol: &Protocol| *protocol != Protocol::P2pCircuit).find_map(|protocol: Protocol| match protocol {
+      Protocol::P2p(peer_id) => Some(peer_id),
+      _ => None,
+    });
+    if let Some(peer_id) = relay {
+      self.reserved.remove(&peer_id);
+    }
+  }
+
+
+  fn reservations(&mut self) -> Vec<Multiaddr> {
+    if !self.private {
+      return Vec::new();
+    }
+    let mut addrs: Vec<Multiaddr> = Vec::new();
+    for (peer_id, addr) in &self.relays {
+      if self.reserved.len() >= self.config.max_relays {
+        break;
+      }
+      if self.reserved.insert(*peer_id) {
+        addrs.push(addr.clone());
+      }
+    }
+    addrs
+  }
+}
@@ -0,0 +1,140 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  cmp::Reverse,
+  fs::File,
+  path::{Path, PathBuf},
+  collections::HashMap,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use libp2p::{multiaddr::Protocol, PeerId, Multiaddr};
+
+use crate::utils::data_path;
+
+
+/// Peers learned from identify and Kademlia, used to rejoin the network on the next start
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PeerCache {
+  pub(crate) peers: HashMap<PeerId, CachedPeer>,
+  #[serde(skip)]
+  changed: bool,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct CachedPeer {
+  pub(crate) addresses: Vec<Multiaddr>,
+  pub(crate) last_seen: DateTime<Utc>,
+}
+
+
+impl PeerCache {
+  fn new(peers: HashMap<PeerId, CachedPeer>) -> Self {
+    Self {
+      peers,
+      changed: false,
+    }
+  }
+
+
+  fn default_path() -> Result<PathBuf> {
+    Ok(data_path("")?.join("peer_cache.json"))
+  }
+
+
+  pub(crate) fn from_default_path() -> Result<Self> {
+    let default_path: PathBuf = Self::default_path()?;
+    if !default_path.exists() {
+      return Ok(Self::default());
+    }
+    Self::from_path(default_path)
+  }
+
+
+  pub(crate) fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let file: File = File::options().truncate(false).read(true).open(path)?;
+    Ok(serde_json::from_reader(file)?)
+  }
+
+
+  pub(crate) fn add_addrs<I: IntoIterator<Item = Multiaddr>>(&mut self, peer_id: PeerId, addresses: I) {
+    let peer: &mut CachedPeer = self.peers.entry(peer_id).or_insert_with(|| CachedPeer::new(Vec::new(), Utc::now()));
+    for mut addr in addresses {
+      if let Some(Protocol::P2p(_)) = addr.iter().last() {
+        addr.pop();
+      }
+      if !peer.addresses.contains(&addr) {
+        peer.addresses.push(addr);
+      }
+    }
+    peer.last_seen = Utc::now();
+    self.changed = true;
+  }
+
+
+  /// Keeps only the `size` most recently seen peers
+  pub(crate) fn truncate(&mut self, size: usize) {
+    if self.peers.len() <= size {
+      return;
+    }
+    let mut peers: Vec<(PeerId, CachedPeer)> = self.peers.drain().collect();
+    peers.sort_by_key(|(_, peer)| Reverse(peer.last_seen));
+    peers.truncate(size);
+    self.peers = peers.into_iter().collect();
+    self.changed = true;
+  }
+
+
+  /// Writes the cache to the default path if anything was learned since the last save
+  pub(crate) fn save_if_changed(&mut self, size: usize) -> Result<()> {
+    if !self.changed {
+      return Ok(());
+    }
+    self.truncate(size);
+    self.save(Self::default_path()?)?;
+    self.changed = false;
+    Ok(())
+  }
+
+
+  pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    let file: File = File::options().create(true).truncate(true).write(true).open(path)?;
+    serde_json::to_writer_pretty(file, self)?;
+    Ok(())
+  }
+}
+
+
+impl Default for PeerCache {
+  fn default() -> Self {
+    Self::new(HashMap::default())
+  }
+}
+
+
+impl CachedPeer {
+  fn new(addresses: Vec<Multiaddr>, last_seen: DateTime<Utc>) -> Self {
+    Self {
+      addresses,
+      last_seen,
+    }
+  }
+}
@@ -0,0 +1,60 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use anyhow::Result;
+use libp2p::StreamProtocol;
+
+
+/// Version of the wire protocol, peers must share the major version to talk to each other
+pub(crate) const PROTOCOL_VERSION: &str = "1.0.0";
+
+
+/// Advertised through identify as `/system/<CHAIN_ID>/<PROTOCOL_VERSION>`
+pub(crate) fn identify_protocol(chain_id: &str) -> String {
+  format!("/system/{chain_id}/{PROTOCOL_VERSION}")
+}
+
+
+pub(crate) fn agent_version() -> String {
+  format!("system/{}", env!("CARGO_PKG_VERSION"))
+}
+
+
+pub(crate) fn kademlia_protocol(chain_id: &str) -> Result<StreamProtocol> {
+  Ok(StreamProtocol::try_from_owned(format!("/system/{chain_id}/kad/1.0.0"))?)
+}
+
+
+/// Gossipsub appends its own version to the prefix
+pub(crate) fn gossipsub_protocol_prefix(chain_id: &str) -> String {
+  format!("/system/{chain_id}/meshsub")
+}
+
+
+/// Whether the identify protocol of a remote peer belongs to the same chain and the same major version
+pub(crate) fn is_compatible(local: &str, remote: &str) -> bool {
+  match (split_protocol(local), split_protocol(remote)) {
+    (Some((local_chain, local_major)), Some((remote_chain, remote_major))) => local_chain == remote_chain && local_major == remote_major,
+    _ => false,
+  }
+}
+
+
+fn split_protocol(protocol: &str) -> Option<(&str, &str)> {
+  let (chain_id, version): (&str, &str) = protocol.strip_prefix("/system/")?.rsplit_once('/')?;
+  Some((chain_id, version.split('.').next()?))
+}
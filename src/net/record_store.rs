@@ -0,0 +1,284 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  borrow::Cow,
+  fs::File,
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Serialize, Deserialize};
+use libp2p::{
+  kad::{
+    store::{self, MemoryStore, MemoryStoreConfig, RecordStore},
+    ProviderRecord,
+    Record,
+    RecordKey,
+  },
+  Multiaddr,
+  PeerId,
+};
+use tracing::debug;
+
+use crate::{net::config::RecordsConfig, utils::data_path};
+
+
+/// Kademlia records kept in memory and synchronized with `<DATA_DIR>/records.json`, so a restarted
+/// node keeps serving the records it was storing. Only the keys provided by this node itself are
+/// saved from the provider records, the others are learned again from the network
+pub(crate) struct DiskStore {
+  memory: MemoryStore,
+  path: Option<PathBuf>,
+  ttl: Duration,
+  changed: bool,
+}
+
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredRecords {
+  records: Vec<StoredRecord>,
+  providers: Vec<StoredProvider>,
+}
+
+
+/// The expiration is saved as a wall clock time, the `Instant` of a record means nothing after a restart
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+  key: Vec<u8>,
+  value: Vec<u8>,
+  publisher: Option<PeerId>,
+  expires: Option<DateTime<Utc>>,
+}
+
+
+#[derive(Serialize, Deserialize)]
+struct StoredProvider {
+  key: Vec<u8>,
+  provider: PeerId,
+  addresses: Vec<Multiaddr>,
+  expires: Option<DateTime<Utc>>,
+}
+
+
+impl DiskStore {
+  /// A store without a path lives only in memory
+  pub(crate) fn new(peer_id: PeerId, config: &RecordsConfig, path: Option<PathBuf>) -> Self {
+    let memory_config: MemoryStoreConfig = MemoryStoreConfig {
+      max_records: config.max_records,
+      max_value_bytes: config.max_value_bytes,
+      max_provided_keys: config.max_provided_keys,
+      ..MemoryStoreConfig::default()
+    };
+
+    Self {
+      memory: MemoryStore::with_config(peer_id, memory_config),
+      path,
+      ttl: config.ttl(),
+      changed: false,
+    }
+  }
+
+
+  fn default_path() -> Result<PathBuf> {
+    Ok(data_path("")?.join("records.json"))
+  }
+
+
+  pub(crate) fn from_default_path(peer_id: PeerId, config: &RecordsConfig) -> Result<Self> {
+    Self::from_path(peer_id, config, Self::default_path()?)
+  }
+
+
+  /// Loads the records that didn't expire yet, the limits of the config still apply
+  pub(crate) fn from_path<P: AsRef<Path>>(peer_id: PeerId, config: &RecordsConfig, path: P) -> Result<Self> {
+    let mut store: Self = Self::new(peer_id, config, Some(path.as_ref().to_path_buf()));
+    if !path.as_ref().exists() {
+      return Ok(store);
+    }
+
+    let file: File = File::options().truncate(false).read(true).open(path)?;
+    let stored_records: StoredRecords = serde_json::from_reader(file)?;
+    debug!(records = stored_records.records.len(), providers = stored_records.providers.len(), "Loading the DHT records");
+    for stored_record in stored_records.records {
+      if let Some(record) = stored_record.into_record() {
+        store.memory.put(record).ok();
+      }
+    }
+    for stored_provider in stored_records.providers {
+      if let Some(provider) = stored_provider.into_provider() {
+        store.memory.add_provider(provider).ok();
+      }
+    }
+    Ok(store)
+  }
+
+
+  /// Drops the expired records and writes the store if anything changed since the last save
+  pub(crate) fn save_if_changed(&mut self) -> Result<()> {
+    let now: Instant = Instant::now();
+    let mut expired: bool = false;
+    self.memory.retain(|_, record: &mut Record| {
+      expired |= record.is_expired(now);
+      !record.is_expired(now)
+    });
+    self.changed |= expired;
+
+    let Some(path) = self.path.clone() else {
+      return Ok(());
+    };
+    if !self.changed {
+      return Ok(());
+    }
+    self.save(path)?;
+    self.changed = false;
+    Ok(())
+  }
+
+
+  pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    let stored_records: StoredRecords = StoredRecords {
+      records: self.memory.records().filter_map(|record: Cow<Record>| StoredRecord::from_record(&record)).collect(),
+      providers: self.memory.provided().filter_map(|provider: Cow<ProviderRecord>| StoredProvider::from_provider(&provider)).collect(),
+    };
+
+    let file: File = File::options().create(true).truncate(true).write(true).open(path)?;
+    serde_json::to_writer_pretty(file, &stored_records)?;
+    Ok(())
+  }
+}
+
+
+impl RecordStore for DiskStore {
+  type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+  type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+
+  fn get(&self, key: &RecordKey) -> Option<Cow<'_, Record>> {
+    self.memory.get(key)
+  }
+
+
+  /// Records accepted from other peers come without an expiration, they get the configured TTL
+  fn put(&mut self, mut record: Record) -> store::Result<()> {
+    record.expires = record.expires.or_else(|| Some(Instant::now() + self.ttl));
+    self.memory.put(record)?;
+    self.changed = true;
+    Ok(())
+  }
+
+
+  fn remove(&mut self, key: &RecordKey) {
+    self.memory.remove(key);
+    self.changed = true;
+  }
+
+
+  fn records(&self) -> Self::RecordsIter<'_> {
+    self.memory.records()
+  }
+
+
+  fn add_provider(&mut self, record: ProviderRecord) -> store::Result<()> {
+    self.memory.add_provider(record)?;
+    self.changed = true;
+    Ok(())
+  }
+
+
+  fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+    self.memory.providers(key)
+  }
+
+
+  fn provided(&self) -> Self::ProvidedIter<'_> {
+    self.memory.provided()
+  }
+
+
+  fn remove_provider(&mut self, key: &RecordKey, provider: &PeerId) {
+    self.memory.remove_provider(key, provider);
+    self.changed = true;
+  }
+}
+
+
+impl StoredRecord {
+  /// `None` for records that already expired
+  fn from_record(record: &Record) -> Option<Self> {
+    Some(Self {
+      key: record.key.to_vec(),
+      value: record.value.clone(),
+      publisher: record.publisher,
+      expires: to_date_time(record.expires)?,
+    })
+  }
+
+
+  fn into_record(self) -> Option<Record> {
+    Some(Record {
+      key: RecordKey::from(self.key),
+      value: self.value,
+      publisher: self.publisher,
+      expires: to_instant(self.expires)?,
+    })
+  }
+}
+
+
+impl StoredProvider {
+  fn from_provider(provider: &ProviderRecord) -> Option<Self> {
+    Some(Self {
+      key: provider.key.to_vec(),
+      provider: provider.provider,
+      addresses: provider.addresses.clone(),
+      expires: to_date_time(provider.expires)?,
+    })
+  }
+
+
+  fn into_provider(self) -> Option<ProviderRecord> {
+    Some(ProviderRecord {
+      key: RecordKey::from(self.key),
+      provider: self.provider,
+      addresses: self.addresses,
+      expires: to_instant(self.expires)?,
+    })
+  }
+}
+
+
+/// `Some(None)` for records that never expire, `None` for the expired ones
+fn to_date_time(expires: Option<Instant>) -> Option<Option<DateTime<Utc>>> {
+  match expires {
+    Some(expires) => {
+      let ttl: TimeDelta = TimeDelta::from_std(expires.checked_duration_since(Instant::now())?).ok()?;
+      Some(Some(Utc::now() + ttl))
+    },
+    None => Some(None),
+  }
+}
+
+
+fn to_instant(expires: Option<DateTime<Utc>>) -> Option<Option<Instant>> {
+  match expires {
+    Some(expires) => Some(Some(Instant::now() + (expires - Utc::now()).to_std().ok()?)),
+    None => Some(None),
+  }
+}
@@ -0,0 +1,41 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::sync::mpsc::Sender;
+
+use libp2p::{kad::{Record, RecordKey}, PeerId};
+
+
+/// Answers a record lookup with the value of the first valid record, `None` when the query finds nothing
+pub(crate) type RecordSender = Sender<Option<Vec<u8>>>;
+
+
+pub(crate) type PeersSender = Sender<Vec<PeerId>>;
+
+
+/// Notified once every message sent so far is published
+pub(crate) type FlushSender = Sender<()>;
+
+
+/// Requests to the network task that aren't gossipsub messages
+pub(crate) enum Request {
+  PutRecord(Record),
+  GetRecord(RecordKey, RecordSender),
+  /// The peers with an open connection
+  ListPeers(PeersSender),
+  Flush(FlushSender),
+}
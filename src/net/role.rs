@@ -0,0 +1,53 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use clap::ValueEnum;
+
+
+/// What a node does in the network, every role runs the same core and only toggles behaviours
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum Role {
+  /// The interactive client, takes part in gossip but doesn't serve others
+  #[value(skip)]
+  Client,
+  /// Validates and relays blocks, serves the DHT and relays connections of unreachable peers
+  #[default]
+  Full,
+  /// Only helps peers join the network: routing, DHT records and relaying, no gossip
+  Bootstrap,
+}
+
+
+impl Role {
+  /// Runs Kademlia in server mode, keeps records on disk, announces itself in the server list
+  /// and may act as a circuit relay
+  pub(crate) fn is_server(&self) -> bool {
+    matches!(self, Self::Full | Self::Bootstrap)
+  }
+
+
+  /// Subscribes to the blockchain topics
+  pub(crate) fn is_gossiping(&self) -> bool {
+    matches!(self, Self::Client | Self::Full)
+  }
+
+
+  /// Appends the blocks it receives to the local chain, the client only stores the blocks it mines
+  pub(crate) fn stores_chain(&self) -> bool {
+    matches!(self, Self::Full)
+  }
+}
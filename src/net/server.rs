@@ -15,159 +15,54 @@
 //!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 
-use std::{
-  time::Duration,
-  net::{Ipv4Addr, Ipv6Addr},
-  path::Path,
-};
-
-use anyhow::{Context, Result};
-use ssh_key::{PrivateKey, Algorithm, rand_core::OsRng};
-use libp2p::{
-  futures::StreamExt,
-  swarm::{Swarm, SwarmEvent, Config},
-  SwarmBuilder,
-  tcp,
-  tls,
-  noise,
-  yamux,
-  multiaddr::{Multiaddr, Protocol},
-  gossipsub::{self, Topic, Sha256Topic, Message},
-  identify,
-  kad,
-  identity::Keypair,
+use std::time::Duration;
+
+use anyhow::Result;
+use ssh_key::PrivateKey;
+use tokio::{
+  sync::{
+    mpsc::{UnboundedSender, UnboundedReceiver, unbounded_channel},
+  },
+  time::{sleep, Instant},
 };
+use tracing::{error, warn};
 
 use crate::net::{
-  behaviour::{Behaviour, BehaviourEvent},
-  server_list::ServerList,
+  config::NetConfig,
+  request::Request,
+  role::Role,
+  send_data::SendData,
+  Net,
 };
 
 
-pub(crate) async fn server_main() -> Result<()> {
-  loop {
-    match main_loop().await {
-      Ok(_) => break,
-      Err(error) => eprintln!("CRITICAL SERVER ERROR: {error}"),
-    }
-  }
-  Ok(())
-}
-
-
-async fn main_loop() -> Result<()> {
-  let key: PrivateKey = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
-  let key: Keypair = Keypair::ed25519_from_bytes(key.key_data().ed25519().context("Key type is not ed25519")?.private.to_bytes())?;
-
-  let behaviour: Behaviour = Behaviour::from_key(key.clone())?;
-
-  let mut swarm: Swarm<Behaviour> = SwarmBuilder::with_existing_identity(key.clone())
-  .with_tokio()
-  .with_tcp(
-    tcp::Config::default(),
-    (tls::Config::new, noise::Config::new),
-    yamux::Config::default,
-  )?
-  .with_quic()
-  .with_dns()?
-  .with_behaviour(|_| -> Behaviour {
-    behaviour
-  })?
-  .with_swarm_config(|config: Config| -> Config {
-    config.with_idle_connection_timeout(Duration::from_secs(u64::MAX))
-  })
-  .build();
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
 
-  let addr_v4_tcp = Multiaddr::empty()
-  .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-  .with(Protocol::Tcp(0));
-
-  let addr_v6_tcp = Multiaddr::empty()
-  .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-  .with(Protocol::Tcp(0));
-  
-  let addr_v4_udp = Multiaddr::empty()
-  .with(Protocol::from(Ipv4Addr::UNSPECIFIED))
-  .with(Protocol::Udp(0))
-  .with(Protocol::QuicV1);
-
-  let addr_v6_udp = Multiaddr::empty()
-  .with(Protocol::from(Ipv6Addr::UNSPECIFIED))
-  .with(Protocol::Udp(0))
-  .with(Protocol::QuicV1);
-  
-  swarm.listen_on(addr_v4_tcp)?;
-  swarm.listen_on(addr_v6_tcp)?;
-  swarm.listen_on(addr_v4_udp)?;
-  swarm.listen_on(addr_v6_udp)?;
-
-  let mut server_list: ServerList = ServerList::default();
-  swarm.behaviour_mut().kademlia.set_mode(Some(kad::Mode::Server));
-
-  let blocks_data_topic: Topic<_> = Sha256Topic::new("blocks_data");
-  let blocks_topic: Topic<_> = Sha256Topic::new("blocks");
-  let blockchain_topic: Topic<_> = Sha256Topic::new("blockchain");
-  swarm.behaviour_mut().gossipsub.subscribe(&blocks_data_topic)?;
-  swarm.behaviour_mut().gossipsub.subscribe(&blocks_topic)?;
-  swarm.behaviour_mut().gossipsub.subscribe(&blockchain_topic)?;
 
+/// Runs a headless node. A node that can't start returns the error, so a service manager sees it,
+/// a node that fails while running is restarted with a delay doubling up to a minute
+pub(crate) async fn server_main(key: PrivateKey, config: NetConfig, role: Role) -> Result<()> {
+  let mut delay: Duration = MIN_RESTART_DELAY;
   loop {
-    tokio::select! {
-      event = swarm.select_next_some() => match event {
-        SwarmEvent::Behaviour(event) => {
-          match event {
-            BehaviourEvent::Identify(event) => match event {
-              identify::Event::Received { peer_id, info: identify::Info { listen_addrs, .. }, .. } => {
-                listen_addrs.iter().for_each(|addr: &Multiaddr| {
-                  swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
-                });
-              },
-  
-              identify::Event::Sent { .. } => (),
-              identify::Event::Pushed { .. } => (),
-              identify::Event::Error { .. } => (),
-            },
-
-            BehaviourEvent::Kademlia(event) => match event {
-              kad::Event::InboundRequest { .. } => (),
-              kad::Event::OutboundQueryProgressed { .. } => (),
-              kad::Event::RoutingUpdated { .. } => (),
-              kad::Event::UnroutablePeer { .. } => (),
-              kad::Event::RoutablePeer { .. } => (),
-              kad::Event::PendingRoutablePeer { .. } => (),
-              kad::Event::ModeChanged { .. } => (),
-            },
-
-            BehaviourEvent::Gossipsub(event) => match event {
-              gossipsub::Event::Message { message: Message { data, .. } , .. } => (),
-              gossipsub::Event::Subscribed { .. } => (),
-              gossipsub::Event::Unsubscribed { .. } => (),
-              gossipsub::Event::GossipsubNotSupported { .. } => (),
-            },
-          }
-        },
+    // Nothing publishes through a headless node yet, the senders only keep the channels open
+    let (_sender, receiver): (UnboundedSender<SendData>, UnboundedReceiver<SendData>) = unbounded_channel();
+    let (_request_sender, request_receiver): (UnboundedSender<Request>, UnboundedReceiver<Request>) = unbounded_channel();
+    let net: Net = Net::from_key(&key, &config, role, receiver, request_receiver)?;
 
-        SwarmEvent::ConnectionEstablished { .. } => (),
-        SwarmEvent::ConnectionClosed { .. } => (),
-        SwarmEvent::IncomingConnection { .. } => (),
-        SwarmEvent::IncomingConnectionError { .. } => (),
-        SwarmEvent::OutgoingConnectionError { .. } => (),
-
-        SwarmEvent::NewListenAddr { listener_id, address } => {
-          server_list.add_addr_and_save::<&Path>(None, swarm.local_peer_id().clone(), listener_id, address)?;
-        },
-
-        SwarmEvent::ExpiredListenAddr { .. } => (),
-        SwarmEvent::ListenerClosed { .. } => (),
-        SwarmEvent::ListenerError { .. } => (),
-        SwarmEvent::Dialing { .. } => (),
-        SwarmEvent::NewExternalAddrCandidate { .. } => (),
-        SwarmEvent::ExternalAddrConfirmed { .. } => (),
-        SwarmEvent::ExternalAddrExpired { .. } => (),
-        SwarmEvent::NewExternalAddrOfPeer { .. } => (),
+    let started: Instant = Instant::now();
+    match net.run().await {
+      Ok(_) => break,
+      Err(error) => error!(%error, "The node failed"),
+    }
 
-        _ => (),
-      },
+    // A node that ran for a while failed for a new reason
+    if started.elapsed() > MAX_RESTART_DELAY {
+      delay = MIN_RESTART_DELAY;
     }
+    warn!(?delay, "Restarting the node");
+    sleep(delay).await;
+    delay = (delay * 2).min(MAX_RESTART_DELAY);
   }
+  Ok(())
 }
@@ -17,37 +17,55 @@
 
 use std::{
   fs::File,
+  net::IpAddr,
   path::{Path, PathBuf},
   collections::HashMap,
 };
 
-use anyhow::{Context, Result};
-use homedir::my_home;
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Serialize, Deserialize};
 use libp2p::{
   PeerId,
   Multiaddr,
-  core::transport::ListenerId,
+  multiaddr::Protocol,
 };
 
+use crate::utils::data_path;
+
+
+/// Servers that were not seen for this long are considered unreachable
+pub(crate) const SERVER_TTL: TimeDelta = TimeDelta::hours(1);
+
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct ServerList {
-  pub(crate) addresses: HashMap<PeerId, HashMap<String, Multiaddr>>,
+  pub(crate) servers: HashMap<PeerId, ServerEntry>,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ServerEntry {
+  pub(crate) addresses: Vec<Multiaddr>,
+  pub(crate) last_seen: DateTime<Utc>,
 }
 
 
 impl ServerList {
-  fn new(addresses: HashMap<PeerId, HashMap<String, Multiaddr>>) -> Self {
+  fn new(servers: HashMap<PeerId, ServerEntry>) -> Self {
     Self {
-      addresses,
+      servers,
     }
   }
 
 
+  fn default_path() -> Result<PathBuf> {
+    Ok(data_path("")?.join("server_list.json"))
+  }
+
+
   pub(crate) fn from_default_path() -> Result<Self> {
-    let default_path: PathBuf = my_home()?.context("context")?.join(".system/server_list.json");
-    Ok(Self::from_path(default_path)?)
+    Self::from_path(Self::default_path()?)
   }
 
   
@@ -57,25 +75,50 @@ impl ServerList {
   }
 
 
-  pub(crate) fn add_addr(&mut self, peer_id: PeerId, listener_id: ListenerId, multiaddr: Multiaddr) {
-    let mut addresses: HashMap<String, Multiaddr> = HashMap::new();
-    if let Some(addr) = self.addresses.get(&peer_id) {
-      addresses = addr.clone();
+  /// Adds the address of the peer unless it is not routable
+  pub(crate) fn add_addr(&mut self, peer_id: PeerId, multiaddr: Multiaddr) {
+    if !is_routable(&multiaddr) {
+      return;
     }
-    addresses.insert(listener_id.to_string(), multiaddr);
-    self.addresses.insert(peer_id, addresses);
+    let entry: &mut ServerEntry = self.servers.entry(peer_id).or_insert_with(|| ServerEntry::new(Vec::new(), Utc::now()));
+    if !entry.addresses.contains(&multiaddr) {
+      entry.addresses.push(multiaddr);
+    }
+    entry.last_seen = Utc::now();
   }
 
 
-  pub(crate) fn add_addr_and_save<P: AsRef<Path>>(&mut self, path: Option<P>, peer_id: PeerId, listener_id: ListenerId, multiaddr: Multiaddr) -> Result<()> {
-    self.add_addr(peer_id, listener_id, multiaddr);
-    if path.is_some() {
-      self.save(path.unwrap())?;
+  pub(crate) fn remove_addr(&mut self, peer_id: &PeerId, multiaddr: &Multiaddr) {
+    if let Some(entry) = self.servers.get_mut(peer_id) {
+      entry.addresses.retain(|addr: &Multiaddr| addr != multiaddr);
+      if entry.addresses.is_empty() {
+        self.servers.remove(peer_id);
+      }
     }
-    else {
-      self.save(my_home()?.context("context")?.join(".system/server_list.json"))?;
+  }
+
+
+  pub(crate) fn remove_peer(&mut self, peer_id: &PeerId) {
+    self.servers.remove(peer_id);
+  }
+
+
+  pub(crate) fn touch(&mut self, peer_id: &PeerId) {
+    if let Some(entry) = self.servers.get_mut(peer_id) {
+      entry.last_seen = Utc::now();
     }
-    Ok(())
+  }
+
+
+  /// Drops the servers that were not seen within `ttl`
+  pub(crate) fn prune(&mut self, ttl: TimeDelta) {
+    let now: DateTime<Utc> = Utc::now();
+    self.servers.retain(|_, entry: &mut ServerEntry| now - entry.last_seen <= ttl);
+  }
+
+
+  pub(crate) fn save_default(&self) -> Result<()> {
+    self.save(Self::default_path()?)
   }
 
 
@@ -91,4 +134,30 @@ impl Default for ServerList {
   fn default() -> Self {
     Self::new(HashMap::default())
   }
-}
\ No newline at end of file
+}
+
+
+impl ServerEntry {
+  fn new(addresses: Vec<Multiaddr>, last_seen: DateTime<Utc>) -> Self {
+    Self {
+      addresses,
+      last_seen,
+    }
+  }
+}
+
+
+/// Whether other hosts may be able to reach the address, loopback, unspecified and link-local ones are not
+pub(crate) fn is_routable(addr: &Multiaddr) -> bool {
+  let ip: IpAddr = match addr.iter().next() {
+    Some(Protocol::Ip4(ip)) => IpAddr::V4(ip),
+    Some(Protocol::Ip6(ip)) => IpAddr::V6(ip),
+    Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)) => return true,
+    _ => return false,
+  };
+
+  match ip {
+    IpAddr::V4(ip) => !(ip.is_loopback() || ip.is_unspecified() || ip.is_link_local() || ip.is_broadcast() || ip.is_multicast()),
+    IpAddr::V6(ip) => !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || ip.is_unicast_link_local()),
+  }
+}
@@ -0,0 +1,133 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::collections::HashMap;
+
+use libp2p::{
+  gossipsub::{
+    MessageAcceptance,
+    PeerScoreParams,
+    PeerScoreThresholds,
+    Sha256Topic,
+    TopicHash,
+    TopicScoreParams,
+  },
+  kad::{Record, RecordKey},
+};
+
+use crate::blockchain::{block::Block, data::Data, user_record::UserRecord};
+
+
+pub(crate) const BLOCKS_DATA_TOPIC: &str = "blocks_data";
+pub(crate) const BLOCKS_TOPIC: &str = "blocks";
+pub(crate) const BLOCKCHAIN_TOPIC: &str = "blockchain";
+
+
+/// Decides whether a gossipsub message is forwarded, rejected messages lower the score of the peer that sent them
+pub(crate) fn validate_message(topic: &TopicHash, data: &[u8]) -> MessageAcceptance {
+  if *topic == topic_hash(BLOCKS_DATA_TOPIC) {
+    match serde_json::from_slice::<Data>(data).map(|data: Data| data.check()) {
+      Ok(Ok(true)) => MessageAcceptance::Accept,
+      _ => MessageAcceptance::Reject,
+    }
+  }
+  else if *topic == topic_hash(BLOCKS_TOPIC) {
+    match serde_json::from_slice::<Block>(data).map(|mut block: Block| block.validate()) {
+      Ok(Ok(true)) => MessageAcceptance::Accept,
+      _ => MessageAcceptance::Reject,
+    }
+  }
+  else {
+    MessageAcceptance::Ignore
+  }
+}
+
+
+/// Only valid user records stored under one of their own keys are kept in the DHT
+pub(crate) fn validate_record(record: &Record) -> bool {
+  match serde_json::from_slice::<UserRecord>(&record.value) {
+    Ok(user_record) => matches!(user_record.check(), Ok(true)) && user_records(&user_record).iter().any(|user_record: &Record| user_record.key == record.key),
+    Err(_) => false,
+  }
+}
+
+
+/// The DHT records of the user registered by an accepted block message
+pub(crate) fn block_records(topic: &TopicHash, data: &[u8]) -> Vec<Record> {
+  if *topic != topic_hash(BLOCKS_TOPIC) {
+    return Vec::new();
+  }
+
+  match serde_json::from_slice::<Block>(data).ok().as_ref().and_then(UserRecord::from_block) {
+    Some(user_record) => user_records(&user_record),
+    None => Vec::new(),
+  }
+}
+
+
+fn user_records(user_record: &UserRecord) -> Vec<Record> {
+  user_record.entries().unwrap_or_default().into_iter().map(|(key, value): (Vec<u8>, Vec<u8>)| {
+    Record::new(RecordKey::new(&key), value)
+  }).collect()
+}
+
+
+pub(crate) fn peer_score_params() -> PeerScoreParams {
+  let mut topics: HashMap<TopicHash, TopicScoreParams> = HashMap::new();
+  for topic in [BLOCKS_DATA_TOPIC, BLOCKS_TOPIC, BLOCKCHAIN_TOPIC] {
+    topics.insert(topic_hash(topic), topic_score_params());
+  }
+
+  PeerScoreParams {
+    topics,
+    ..PeerScoreParams::default()
+  }
+}
+
+
+pub(crate) fn peer_score_thresholds() -> PeerScoreThresholds {
+  PeerScoreThresholds::default()
+}
+
+
+/// Rewards first deliveries and heavily penalizes invalid messages, mesh delivery
+/// penalties stay disabled because traffic is too sparse to expect a steady rate
+fn topic_score_params() -> TopicScoreParams {
+  TopicScoreParams {
+    topic_weight: 1.0,
+    first_message_deliveries_weight: 1.0,
+    first_message_deliveries_cap: 100.0,
+    mesh_message_deliveries_weight: 0.0,
+    mesh_failure_penalty_weight: 0.0,
+    invalid_message_deliveries_weight: -100.0,
+    invalid_message_deliveries_decay: 0.9,
+    ..TopicScoreParams::default()
+  }
+}
+
+
+/// The name of a known topic, gossipsub only carries the hashes
+pub(crate) fn topic_name(topic: &TopicHash) -> &'static str {
+  [BLOCKS_DATA_TOPIC, BLOCKS_TOPIC, BLOCKCHAIN_TOPIC].into_iter()
+  .find(|name: &&str| topic_hash(name) == *topic)
+  .unwrap_or("unknown")
+}
+
+
+fn topic_hash(topic: &str) -> TopicHash {
+  Sha256Topic::new(topic).hash()
+}
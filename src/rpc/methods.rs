@@ -0,0 +1,220 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::fmt::Display;
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use ssh_key::PublicKey;
+use tracing::debug;
+
+use crate::{
+  blockchain::{block::Block, chain, data::user::UserData, ledger::Ledger, user_record::UserQuery},
+  user::User,
+  utils::{from_hex, to_hex},
+};
+
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+
+#[derive(Deserialize)]
+struct Request {
+  #[serde(default)]
+  id: Value,
+  method: String,
+  #[serde(default)]
+  params: Value,
+}
+
+
+#[derive(Serialize)]
+pub(crate) struct Response {
+  jsonrpc: &'static str,
+  id: Value,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<Error>,
+}
+
+
+#[derive(Serialize)]
+struct Error {
+  code: i64,
+  message: String,
+}
+
+
+#[derive(Deserialize)]
+struct BalanceParams {
+  /// The logged in user when missing
+  public_key: Option<String>,
+}
+
+
+#[derive(Deserialize)]
+struct UserParams {
+  /// User name or public key
+  query: String,
+}
+
+
+#[derive(Deserialize)]
+struct TransferParams {
+  /// User name or public key of the recipient
+  to: String,
+  amount: f64,
+}
+
+
+#[derive(Deserialize)]
+struct BlockParams {
+  id: Option<u128>,
+  /// Hexadecimal hash of the block
+  hash: Option<String>,
+}
+
+
+impl Response {
+  fn result(id: Value, result: Value) -> Self {
+    Self {
+      jsonrpc: "2.0",
+      id,
+      result: Some(result),
+      error: None,
+    }
+  }
+
+
+  fn error(id: Value, error: Error) -> Self {
+    Self {
+      jsonrpc: "2.0",
+      id,
+      result: None,
+      error: Some(error),
+    }
+  }
+}
+
+
+impl Error {
+  fn new<M: Display>(code: i64, message: M) -> Self {
+    Self {
+      code,
+      message: message.to_string(),
+    }
+  }
+}
+
+
+impl From<anyhow::Error> for Error {
+  fn from(error: anyhow::Error) -> Self {
+    Self::new(SERVER_ERROR, error)
+  }
+}
+
+
+/// Answers one JSON-RPC 2.0 request, the calls block on the network and the chain store
+pub(crate) fn handle(user: &User, request: &str) -> Response {
+  let request: Value = match serde_json::from_str(request) {
+    Ok(request) => request,
+    Err(error) => return Response::error(Value::Null, Error::new(PARSE_ERROR, error)),
+  };
+  let request: Request = match serde_json::from_value(request) {
+    Ok(request) => request,
+    Err(error) => return Response::error(Value::Null, Error::new(INVALID_REQUEST, error)),
+  };
+
+  debug!(method = %request.method, "JSON-RPC call");
+  match call(user, &request.method, request.params) {
+    Ok(result) => Response::result(request.id, result),
+    Err(error) => Response::error(request.id, error),
+  }
+}
+
+
+fn call(user: &User, method: &str, params: Value) -> Result<Value, Error> {
+  let result: Value = match method {
+    "get_balance" => {
+      let params: BalanceParams = parse_params(params)?;
+      let balance: f64 = match params.public_key {
+        Some(public_key) => Ledger::load()?.get_balance(&parse_public_key(&public_key)?),
+        None => user.get_balance()?,
+      };
+      json!({ "balance": balance })
+    },
+
+    "get_user" => {
+      let params: UserParams = parse_params(params)?;
+      let user_data: UserData = user.find_user(&UserQuery::parse(&params.query))?;
+      to_value(&user_data)?
+    },
+
+    "send_transfer" => {
+      let params: TransferParams = parse_params(params)?;
+      let recipient: UserData = user.find_user(&UserQuery::parse(&params.to))?;
+      let block: Block = user.transfer(&parse_public_key(&recipient.get_public_key())?, params.amount)?;
+      json!({ "block_id": block.get_id(), "hash": to_hex(&block.get_hash()) })
+    },
+
+    "get_block" => {
+      let params: BlockParams = parse_params(params)?;
+      let block: Option<Block> = match (params.id, params.hash) {
+        (Some(id), None) => chain::block_by_id(id)?,
+        (None, Some(hash)) => chain::block_by_hash(&from_hex(&hash)?)?,
+        _ => return Err(Error::new(INVALID_PARAMS, "Pass either the id or the hash of the block")),
+      };
+      to_value(&block)?
+    },
+
+    "get_tip" => to_value(&chain::tip()?)?,
+
+    "list_peers" => {
+      let peers: Vec<String> = user.list_peers()?.iter().map(ToString::to_string).collect();
+      to_value(&peers)?
+    },
+
+    _ => return Err(Error::new(METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+  };
+  Ok(result)
+}
+
+
+/// Methods without required parameters may be called without `params`
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, Error> {
+  let params: Value = match params {
+    Value::Null => Value::Object(Map::new()),
+    params => params,
+  };
+  serde_json::from_value(params).map_err(|error: serde_json::Error| Error::new(INVALID_PARAMS, error))
+}
+
+
+fn parse_public_key(public_key: &str) -> Result<PublicKey, Error> {
+  PublicKey::from_openssh(public_key).map_err(|error: ssh_key::Error| Error::new(INVALID_PARAMS, error))
+}
+
+
+fn to_value<S: Serialize>(value: &S) -> Result<Value> {
+  serde_json::to_value(value).context("Failed to serialize the result")
+}
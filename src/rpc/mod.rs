@@ -0,0 +1,96 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+mod methods;
+
+
+use std::{
+  fs::remove_file,
+  net::SocketAddr,
+  os::unix::fs::FileTypeExt,
+  path::PathBuf,
+  sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use tokio::{
+  io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
+  net::{TcpListener, UnixListener},
+  task,
+};
+use tracing::{debug, info};
+
+use crate::{rpc::methods::{handle, Response}, user::User};
+
+
+/// Where the JSON-RPC server listens
+pub(crate) enum Endpoint {
+  Tcp(SocketAddr),
+  Unix(PathBuf),
+}
+
+
+/// Serves newline-delimited JSON-RPC 2.0 requests on behalf of the logged in user until an error occurs
+pub(crate) async fn serve(endpoint: Endpoint, user: User) -> Result<()> {
+  let user: Arc<User> = Arc::new(user);
+  match endpoint {
+    Endpoint::Tcp(addr) => {
+      let listener: TcpListener = TcpListener::bind(addr).await?;
+      info!(addr = %listener.local_addr()?, "Serving JSON-RPC");
+      loop {
+        let (stream, peer_addr) = listener.accept().await?;
+        debug!(%peer_addr, "JSON-RPC connection");
+        task::spawn(serve_connection(stream, user.clone()));
+      }
+    },
+
+    Endpoint::Unix(path) => {
+      // A socket left by a previous run would make the bind fail, anything else is not ours to delete
+      if let Ok(metadata) = path.symlink_metadata() {
+        if !metadata.file_type().is_socket() {
+          bail!("{} exists and is not a socket", path.display());
+        }
+        remove_file(&path)?;
+      }
+      let listener: UnixListener = UnixListener::bind(&path)?;
+      info!(path = %path.display(), "Serving JSON-RPC");
+      loop {
+        let (stream, _) = listener.accept().await?;
+        debug!("JSON-RPC connection");
+        task::spawn(serve_connection(stream, user.clone()));
+      }
+    },
+  }
+}
+
+
+async fn serve_connection<S: AsyncRead + AsyncWrite>(stream: S, user: Arc<User>) -> Result<()> {
+  let (reader, mut writer): (ReadHalf<S>, WriteHalf<S>) = split(stream);
+  let mut lines: Lines<BufReader<ReadHalf<S>>> = BufReader::new(reader).lines();
+  while let Some(line) = lines.next_line().await? {
+    if line.trim().is_empty() {
+      continue;
+    }
+
+    let user: Arc<User> = user.clone();
+    let response: Response = task::spawn_blocking(move || handle(&user, &line)).await?;
+    let mut response: Vec<u8> = serde_json::to_vec(&response)?;
+    response.push(b'\n');
+    writer.write_all(&response).await?;
+  }
+  Ok(())
+}
@@ -18,10 +18,13 @@
 use std::io::{stdin, stdout, Write};
 
 use anyhow::{Context, Result};
+use ssh_key::PublicKey;
 use strum::{EnumIter, EnumMessage, IntoEnumIterator};
 
 use crate::{
-  ui::menu::Menu,
+  blockchain::{block::Block, data::user::UserData, user_record::UserQuery},
+  config::UiConfig,
+  ui::{menu::Menu, password::{new_password, prompt_password}},
   user::User,
 };
 
@@ -30,6 +33,12 @@ use crate::{
 pub(crate) enum Main {
   #[strum(message = "Transfer", detailed_message = "Transfer money to the user")]
   Transfer,
+  #[strum(message = "Find user", detailed_message = "Find the user by the user name or the public key")]
+  FindUser,
+  #[strum(message = "Change password", detailed_message = "Encrypt the key with a new password")]
+  ChangePassword,
+  #[strum(message = "Rotate key", detailed_message = "Move the user and the money to a new key, the old key stops working")]
+  RotateKey,
 }
 
 
@@ -41,21 +50,29 @@ impl Default for Main {
 
 
 impl Menu for Main {
-  fn show_menu(&self) -> Result<()> {
+  fn show_menu(&self, config: &UiConfig) -> Result<()> {
     for (i, e) in Self::iter().enumerate() {
       let action_name: String = e.get_message().context("The name of the action was not found")?.to_string();
       let action_description: String = e.get_detailed_message().context("The description of the action was not found")?.to_string();
-      println!("[{}] [{action_name}] -> {action_description}", i + 1);
+      if config.show_descriptions {
+        println!("[{}] [{action_name}] -> {action_description}", i + 1);
+      } else {
+        println!("[{}] [{action_name}]", i + 1);
+      }
     }
-    print!("~$ ");
+    print!("{}", config.prompt);
     stdout().flush()?;
     Ok(())
   }
 
 
-  fn process_action(&self, user: &mut User) -> Result<Box<dyn Menu>> {
+  fn process_action(&self, user: &mut User) -> Result<Option<Box<dyn Menu>>> {
     let mut action: String = String::new();
-    stdin().read_line(&mut action)?;
+    // The client keeps going after failed actions, the end of the input is the only way out
+    if stdin().read_line(&mut action)? == 0 {
+      println!();
+      return Ok(None);
+    }
 
     let menu: Box<dyn Menu> = match action.to_lowercase().trim() {
       "1" => {
@@ -67,19 +84,114 @@ impl Menu for Main {
         Self::default_menu()
       },
 
+      "2" => {
+        Self::find_user(user)?;
+        Self::default_menu()
+      },
+      "find" => {
+        Self::find_user(user)?;
+        Self::default_menu()
+      },
+
+      "3" => {
+        Self::change_password(user)?;
+        Self::default_menu()
+      },
+      "password" => {
+        Self::change_password(user)?;
+        Self::default_menu()
+      },
+
+      "4" => {
+        Self::rotate_key(user)?;
+        Self::default_menu()
+      },
+      "rotate" => {
+        Self::rotate_key(user)?;
+        Self::default_menu()
+      },
+
       _ => {
         println!("Unknown action");
         Self::default_menu()
       },
     };
 
-    Ok(menu)
+    Ok(Some(menu))
   }
 }
 
 
 impl Main {
   fn transfer_money(user: &mut User) -> Result<()> {
+    let mut recipient: String = String::new();
+    print!("Enter the user name or the public key of the recipient: ");
+    stdout().flush()?;
+    stdin().read_line(&mut recipient)?;
+
+    let mut amount: String = String::new();
+    print!("Enter the amount: ");
+    stdout().flush()?;
+    stdin().read_line(&mut amount)?;
+    let Ok(amount) = amount.trim().parse::<f64>() else {
+      println!("The amount must be a number");
+      return Ok(());
+    };
+
+    let result: Result<Block> = user.find_user(&UserQuery::parse(recipient.trim())).and_then(|recipient: UserData| {
+      user.transfer(&PublicKey::from_openssh(&recipient.get_public_key())?, amount)
+    });
+    match result {
+      Ok(block) => println!("Transferred {amount} in block {}", block.get_id()),
+      Err(error) => println!("{error}"),
+    }
+    Ok(())
+  }
+
+
+  fn find_user(user: &User) -> Result<()> {
+    let mut query: String = String::new();
+    print!("Enter the user name or the public key: ");
+    stdout().flush()?;
+    stdin().read_line(&mut query)?;
+
+    match user.find_user(&UserQuery::parse(query.trim())) {
+      Ok(user_data) => {
+        println!("User name: {}", user_data.get_user_name());
+        println!("Name: {} {}", user_data.get_first_name(), user_data.get_last_name());
+        println!("Public key: {}", user_data.get_public_key());
+      },
+      Err(error) => println!("{error}"),
+    }
+    Ok(())
+  }
+
+
+  fn change_password(user: &User) -> Result<()> {
+    // The key is already decrypted, the old password only proves it is still the same person
+    if User::decrypt_key(&prompt_password("Enter the current password: ")?)?.is_none() {
+      println!("Wrong password");
+      return Ok(());
+    }
+
+    user.change_password(&new_password()?)?;
+    println!("The password was changed");
+    Ok(())
+  }
+
+
+  fn rotate_key(user: &mut User) -> Result<()> {
+    // The new key is encrypted with the same password
+    let password: String = prompt_password("Enter the current password: ")?;
+    if User::decrypt_key(&password)?.is_none() {
+      println!("Wrong password");
+      return Ok(());
+    }
+
+    match user.rotate_key(&password) {
+      Ok(block) => println!("Moved to a new key in block {}", block.get_id()),
+      Err(error) => println!("{error}"),
+    }
     Ok(())
   }
 
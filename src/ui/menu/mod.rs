@@ -20,10 +20,11 @@ pub(crate) mod main;
 
 use anyhow::Result;
 
-use crate::user::User;
+use crate::{config::UiConfig, user::User};
 
 
 pub(crate) trait Menu {
-  fn show_menu(&self) -> Result<()>;
-  fn process_action(&self, user: &mut User) -> Result<Box<dyn Menu>>;
+  fn show_menu(&self, config: &UiConfig) -> Result<()>;
+  /// The next menu, `None` when the input ended
+  fn process_action(&self, user: &mut User) -> Result<Option<Box<dyn Menu>>>;
 }
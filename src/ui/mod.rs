@@ -16,65 +16,155 @@
 
 
 mod menu;
+pub(crate) mod password;
 
 
 use std::io::{stdin, stdout, Stdin, Stdout, Write};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use ssh_key::PrivateKey;
 
 use crate::{
-  utils::data_path,
-  ui::menu::{Menu, main::Main},
-  user::User,
+  blockchain::ledger::Ledger,
+  config::{Config, UiConfig},
+  ui::{menu::{Menu, main::Main}, password::{new_password, PasswordSource}},
+  user::{keystore::{account_path, accounts, check_account_name, key_path, select_account, selected_account}, User},
 };
 
 
 pub(crate) struct UI {
   menu: Box<dyn Menu>,
   user: User,
+  config: UiConfig,
 }
 
 
 impl UI {
-  fn new<M: Menu + 'static>(menu: Box<M>, user: User) -> Self {
+  fn new<M: Menu + 'static>(menu: Box<M>, user: User, config: UiConfig) -> Self {
     Self {
       menu,
       user,
+      config,
     }
   }
 
 
   pub(crate) fn show_menu(&self) -> Result<()> {
-    self.menu.show_menu()?;
+    self.menu.show_menu(&self.config)?;
     Ok(())
   }
 
 
-  pub(crate) fn process_action(&mut self) -> Result<()> {
-    self.menu = self.menu.process_action(&mut self.user)?;
-    Ok(())
+  /// `false` when the input ended
+  pub(crate) fn process_action(&mut self) -> Result<bool> {
+    match self.menu.process_action(&mut self.user)? {
+      Some(menu) => {
+        self.menu = menu;
+        Ok(true)
+      },
+      None => Ok(false),
+    }
+  }
+
+
+  /// Waits until the mined blocks reach the network, `false` when no peer took them in time
+  pub(crate) fn flush(&self) -> Result<bool> {
+    self.user.flush()
   }
 
 
-  pub(crate) fn create() -> Result<Self> {
-    let user: User = if !data_path("")?.join("key.pem").exists() {
-      Self::create_user()?
+  pub(crate) fn create(config: &Config, password: &PasswordSource) -> Result<Self> {
+    let user: User = if Self::choose_account()? {
+      User::from_key(password.unlock()?, config)?
     } else {
-      let mut password: String = String::new();
-      print!("Enter password: ");
-      stdout().flush()?;
-      stdin().read_line(&mut password)?;
-      User::from_password(password.trim().to_string())?
+      Self::create_user(config)?
     };
 
     Ok(Self::new(
       Main::default_menu(),
       user,
+      config.ui.clone(),
     ))
   }
 
 
-  fn create_user() -> Result<User> {
+  /// Selects an existing account, `false` when a new one is registered
+  fn choose_account() -> Result<bool> {
+    if selected_account().is_some() {
+      return Ok(key_path()?.exists());
+    }
+
+    let accounts: Vec<String> = accounts()?;
+    match accounts.as_slice() {
+      [] => return Ok(false),
+      // An account unknown to the local chain may have never been registered, so a new one can be registered instead
+      [account] if Self::is_registered(account)? => {
+        select_account(account.clone())?;
+        return Ok(true);
+      },
+      _ => (),
+    }
+
+    for (i, account) in accounts.iter().enumerate() {
+      println!("[{}] {account}", i + 1);
+    }
+    loop {
+      let mut choice: String = String::new();
+      print!("Choose the account, an empty line registers a new one: ");
+      stdout().flush()?;
+      if stdin().read_line(&mut choice)? == 0 {
+        bail!("The input ended before an account was chosen");
+      }
+
+      let choice: &str = choice.trim();
+      if choice.is_empty() {
+        return Ok(false);
+      }
+      let account: Option<&String> = match choice.parse::<usize>() {
+        Ok(i) => i.checked_sub(1).and_then(|i: usize| accounts.get(i)),
+        Err(_) => accounts.iter().find(|account: &&String| *account == choice),
+      };
+      match account {
+        Some(account) => {
+          select_account(account.clone())?;
+          return Ok(true);
+        },
+        None => println!("Unknown account"),
+      }
+    }
+  }
+
+
+  /// The public key is stored unencrypted, no password is needed
+  fn is_registered(account: &str) -> Result<bool> {
+    let key: PrivateKey = PrivateKey::read_openssh_file(&account_path(account)?)?;
+    Ok(Ledger::load()?.is_known(key.public_key()))
+  }
+
+
+  /// The user name, unless it is taken or can't be a file name
+  fn new_account(user_name: &str) -> Result<String> {
+    let accounts: Vec<String> = accounts()?;
+    let mut account: String = user_name.to_string();
+    loop {
+      match check_account_name(&account) {
+        Ok(()) if accounts.contains(&account) => println!("The account {account} already exists"),
+        Ok(()) => return Ok(account),
+        Err(error) => println!("{error}"),
+      }
+
+      account.clear();
+      print!("Enter the account name: ");
+      stdout().flush()?;
+      if stdin().read_line(&mut account)? == 0 {
+        bail!("The input ended before the account name");
+      }
+      account = account.trim().to_string();
+    }
+  }
+
+
+  fn create_user(config: &Config) -> Result<User> {
     let stdin: Stdin = stdin();
     let mut stdout: Stdout = stdout();
 
@@ -93,18 +183,21 @@ impl UI {
     stdout.flush()?;
     stdin.read_line(&mut user_name)?;
 
-    let mut password: String = String::new();
-    print!("Enter password: ");
-    stdout.flush()?;
-    stdin.read_line(&mut password)?;
+    if selected_account().is_none() {
+      select_account(Self::new_account(user_name.trim())?)?;
+    }
+
+    let password: String = new_password()?;
 
     let user: User = User::create(
       first_name.trim(),
       last_name.trim(),
       user_name.trim(),
-      password.trim(),
+      password,
+      config,
     )?;
 
     Ok(user)
   }
 }
+
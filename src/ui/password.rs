@@ -0,0 +1,158 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  env::var,
+  fs::read_to_string,
+  io::{stdin, stdout, IsTerminal, Write},
+  path::PathBuf,
+  process::{Command, Output},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use ssh_key::PrivateKey;
+
+use crate::user::User;
+
+
+/// Takes precedence over the other sources, for CI secrets
+pub(crate) const PASSWORD_ENV: &str = "SYSTEM_PASSWORD";
+pub(crate) const MIN_PASSWORD_LENGTH: usize = 8;
+/// Typed passwords only, the other sources would give the same password again
+pub(crate) const MAX_PASSWORD_ATTEMPTS: usize = 3;
+
+
+/// Where the password of the local key comes from when it isn't typed in
+#[derive(Clone, Default)]
+pub(crate) struct PasswordSource {
+  file: Option<PathBuf>,
+  command: Option<String>,
+}
+
+
+impl PasswordSource {
+  pub(crate) fn new(file: Option<PathBuf>, command: Option<String>) -> Self {
+    Self {
+      file,
+      command,
+    }
+  }
+
+
+  /// `SYSTEM_PASSWORD`, then the file, then the output of the command, the prompt otherwise
+  pub(crate) fn read(&self) -> Result<String> {
+    if let Ok(password) = var(PASSWORD_ENV) {
+      return Ok(password);
+    }
+    if let Some(path) = &self.file {
+      let password: String = read_to_string(path).with_context(|| format!("Failed to read the password file {}", path.display()))?;
+      return Ok(strip_line_ending(password));
+    }
+    if let Some(command) = &self.command {
+      let output: Output = Command::new("sh").arg("-c").arg(command).output()?;
+      ensure!(output.status.success(), "The password command failed with {}", output.status);
+      return Ok(strip_line_ending(String::from_utf8(output.stdout)?));
+    }
+    prompt_password("Enter password: ")
+  }
+
+
+  /// Decrypts the local key, a typed password may be retried
+  pub(crate) fn unlock(&self) -> Result<PrivateKey> {
+    Ok(self.unlock_with_password()?.0)
+  }
+
+
+  /// The key and the password that decrypted it, for writing a new key with the same password
+  pub(crate) fn unlock_with_password(&self) -> Result<(PrivateKey, String)> {
+    let attempts: usize = if self.is_interactive() { MAX_PASSWORD_ATTEMPTS } else { 1 };
+
+    for attempt in 1..=attempts {
+      let password: String = self.read()?;
+      if let Some(key) = User::decrypt_key(&password)? {
+        return Ok((key, password));
+      }
+      if attempt < attempts {
+        println!("Wrong password, try again");
+      }
+    }
+    bail!("Wrong password")
+  }
+
+
+  fn is_interactive(&self) -> bool {
+    var(PASSWORD_ENV).is_err() && self.file.is_none() && self.command.is_none()
+  }
+}
+
+
+/// Hidden on a terminal, piped passwords are read as a line of stdin
+pub(crate) fn prompt_password(prompt: &str) -> Result<String> {
+  if stdin().is_terminal() {
+    return Ok(rpassword::prompt_password(prompt)?);
+  }
+
+  let mut password: String = String::new();
+  print!("{prompt}");
+  stdout().flush()?;
+  if stdin().read_line(&mut password)? == 0 {
+    bail!("The input ended before the password");
+  }
+  Ok(strip_line_ending(password))
+}
+
+
+/// Asks until the password is strong enough and repeated correctly
+pub(crate) fn new_password() -> Result<String> {
+  loop {
+    let password: String = prompt_password("Enter password: ")?;
+    if let Some(weakness) = weakness(&password) {
+      println!("{weakness}");
+      continue;
+    }
+    if prompt_password("Repeat password: ")? != password {
+      println!("The passwords don't match");
+      continue;
+    }
+    return Ok(password);
+  }
+}
+
+
+/// Why the password is too weak, `None` when it is strong enough
+fn weakness(password: &str) -> Option<String> {
+  if password.chars().count() < MIN_PASSWORD_LENGTH {
+    return Some(format!("The password must have at least {MIN_PASSWORD_LENGTH} characters"));
+  }
+
+  let classes: usize = [
+    password.chars().any(char::is_lowercase),
+    password.chars().any(char::is_uppercase),
+    password.chars().any(char::is_numeric),
+    password.chars().any(|c: char| !c.is_alphanumeric()),
+  ].into_iter().filter(|has| *has).count();
+  if classes < 2 {
+    return Some("The password must mix at least two of lowercase and uppercase letters, digits and symbols".to_string());
+  }
+  None
+}
+
+
+/// Files and commands end the password with a new line, other whitespace belongs to it
+fn strip_line_ending(password: String) -> String {
+  password.trim_end_matches(['\n', '\r']).to_string()
+}
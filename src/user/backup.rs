@@ -0,0 +1,210 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  collections::BTreeMap,
+  fs::{read, File, OpenOptions},
+  io::{Read, Write},
+  os::unix::fs::OpenOptionsExt,
+  path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha3::{Digest, Sha3_256};
+use ssh_key::{PrivateKey, PublicKey};
+use tar::{Archive, Builder, Header};
+use tracing::warn;
+use xz::{read::XzDecoder, write::XzEncoder};
+
+use crate::{
+  blockchain::{chain, data::user::UserData, user_record::UserQuery},
+  user::keystore::{import_key, key_path, selected_account},
+  utils::{data_path, to_hex},
+};
+
+
+/// Backups are told apart from exported keys by the xz header
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const BACKUP_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const KEY_FILE: &str = "key.pem";
+const CONFIG_FILE: &str = "config.json";
+
+
+/// The first file of a backup, the other files are checked against it on import
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+  version: u32,
+  account: String,
+  public_key: String,
+  /// The registration of the user in the chain of the exporting machine
+  user: Option<UserData>,
+  created: DateTime<Utc>,
+  /// SHA3-256 of every other file of the backup
+  files: BTreeMap<String, String>,
+}
+
+
+/// An exported key or a backup that passed the integrity checks
+pub(crate) struct Import {
+  account: String,
+  key: PrivateKey,
+  config: Option<Vec<u8>>,
+}
+
+
+impl Import {
+  /// Reads an exported key or a backup, exported keys are named after the file
+  pub(crate) fn read(input: &Path) -> Result<Self> {
+    let content: Vec<u8> = read(input).with_context(|| format!("Failed to read {}", input.display()))?;
+    if content.starts_with(XZ_MAGIC) {
+      return Self::read_backup(&content);
+    }
+
+    let key: PrivateKey = PrivateKey::from_openssh(&content).context("The file is neither an exported key nor a backup")?;
+    let account: String = input.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+    Ok(Self {
+      account,
+      key,
+      config: None,
+    })
+  }
+
+
+  fn read_backup(content: &[u8]) -> Result<Self> {
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for entry in Archive::new(XzDecoder::new(content)).entries()? {
+      let mut entry: tar::Entry<XzDecoder<&[u8]>> = entry?;
+      let name: String = entry.path()?.to_string_lossy().to_string();
+      let mut data: Vec<u8> = Vec::new();
+      entry.read_to_end(&mut data)?;
+      ensure!(files.insert(name.clone(), data).is_none(), "The backup has {name} twice");
+    }
+
+    let manifest: Manifest = serde_json::from_slice(&files.remove(MANIFEST_FILE).context("The backup has no manifest")?)?;
+    ensure!(manifest.version == BACKUP_VERSION, "Backups of version {} are not supported", manifest.version);
+    ensure!(files.keys().eq(manifest.files.keys()), "The files of the backup don't match the manifest");
+    for (name, data) in &files {
+      ensure!(checksum(data) == manifest.files[name], "{name} of the backup is corrupted");
+    }
+
+    let key: PrivateKey = PrivateKey::from_openssh(files.get(KEY_FILE).context("The backup has no key")?)?;
+    ensure!(
+      key.public_key().key_data() == PublicKey::from_openssh(&manifest.public_key)?.key_data(),
+      "The key of the backup doesn't match the manifest",
+    );
+    Ok(Self {
+      account: manifest.account,
+      key,
+      config: files.remove(CONFIG_FILE),
+    })
+  }
+
+
+  /// The private key matches the public one only with the right password
+  pub(crate) fn check_password(&self, password: &str) -> Result<bool> {
+    ensure!(self.key.is_encrypted(), "Only encrypted keys are imported");
+    match self.key.decrypt(password) {
+      Ok(_) => Ok(true),
+      Err(ssh_key::Error::Crypto) => Ok(false),
+      Err(error) => Err(error.into()),
+    }
+  }
+
+
+  /// Stores the key as a new account, the config of the backup is used only when there is none
+  pub(crate) fn save(&self, account: &str) -> Result<()> {
+    import_key(account, &self.key)?;
+
+    if let Some(config) = &self.config {
+      match create_new(&data_path("")?.join(CONFIG_FILE)) {
+        Ok(mut file) => file.write_all(config)?,
+        Err(_) => warn!("The data directory has a config already, the config of the backup was skipped"),
+      }
+    }
+    Ok(())
+  }
+
+
+  pub(crate) fn get_account(&self) -> String {
+    self.account.clone()
+  }
+
+
+  pub(crate) fn get_public_key(&self) -> &PublicKey {
+    self.key.public_key()
+  }
+}
+
+
+/// Copies the encrypted key of the selected account, the output is never overwritten
+pub(crate) fn export_key(output: &Path) -> Result<()> {
+  create_new(output)?.write_all(&read(key_path()?)?)?;
+  Ok(())
+}
+
+
+/// Writes an xz compressed tar archive with the manifest, the encrypted key and the config
+pub(crate) fn export_backup(output: &Path) -> Result<()> {
+  let key: Vec<u8> = read(key_path()?)?;
+  let public_key: PublicKey = PrivateKey::from_openssh(&key)?.public_key().clone();
+  let mut files: Vec<(&str, Vec<u8>)> = vec![(KEY_FILE, key)];
+  let config_path: PathBuf = data_path("")?.join(CONFIG_FILE);
+  if config_path.exists() {
+    files.push((CONFIG_FILE, read(config_path)?));
+  }
+
+  let manifest: Manifest = Manifest {
+    version: BACKUP_VERSION,
+    account: selected_account().context("No account was selected")?,
+    public_key: public_key.to_openssh()?,
+    user: chain::find_user(&UserQuery::PublicKey(public_key))?,
+    created: Utc::now(),
+    files: files.iter().map(|(name, data): &(&str, Vec<u8>)| (name.to_string(), checksum(data))).collect(),
+  };
+
+  let mut archive: Builder<XzEncoder<File>> = Builder::new(XzEncoder::new(create_new(output)?, 6));
+  append(&mut archive, MANIFEST_FILE, &serde_json::to_vec_pretty(&manifest)?)?;
+  for (name, data) in &files {
+    append(&mut archive, name, data)?;
+  }
+  archive.into_inner()?.finish()?;
+  Ok(())
+}
+
+
+fn append(archive: &mut Builder<XzEncoder<File>>, name: &str, data: &[u8]) -> Result<()> {
+  let mut header: Header = Header::new_gnu();
+  header.set_size(data.len() as u64);
+  header.set_mode(0o600);
+  header.set_mtime(Utc::now().timestamp().try_into()?);
+  archive.append_data(&mut header, name, data)?;
+  Ok(())
+}
+
+
+/// Keys are secrets even when encrypted, so the files are private to the user
+fn create_new(path: &Path) -> Result<File> {
+  OpenOptions::new().write(true).create_new(true).mode(0o600).open(path).map_err(|error| anyhow!("Failed to create {}: {error}", path.display()))
+}
+
+
+fn checksum(data: &[u8]) -> String {
+  to_hex(&Sha3_256::digest(data))
+}
@@ -0,0 +1,148 @@
+//!   system. The program that gives access to the system.
+//!   Copyright (C) 2024  Andrew Kozmin
+//!   
+//!   This program is free software: you can redistribute it and/or modify
+//!   it under the terms of the GNU Affero General Public License as published
+//!   by the Free Software Foundation, either version 3 of the License, or
+//!   (at your option) any later version.
+//!   
+//!   This program is distributed in the hope that it will be useful,
+//!   but WITHOUT ANY WARRANTY; without even the implied warranty of
+//!   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//!   GNU Affero General Public License for more details.
+//!   
+//!   You should have received a copy of the GNU Affero General Public License
+//!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::{
+  fs::{copy, hard_link, read_dir, remove_file, rename},
+  io::ErrorKind,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use ssh_key::{PrivateKey, LineEnding, rand_core::OsRng};
+use tracing::info;
+
+use crate::utils::{data_dir, data_path};
+
+
+/// The account used by this run, selected once like the data directory
+static ACCOUNT: OnceLock<String> = OnceLock::new();
+/// Accounts created before the keystore get this name
+const DEFAULT_ACCOUNT: &str = "default";
+
+
+pub(crate) fn select_account<S: Into<String>>(account: S) -> Result<()> {
+  let account: String = account.into();
+  check_account_name(&account)?;
+  ACCOUNT.set(account).ok().context("The account has already been selected")?;
+  Ok(())
+}
+
+
+pub(crate) fn selected_account() -> Option<String> {
+  ACCOUNT.get().cloned()
+}
+
+
+/// The names of the accounts with a key in `<DATA_DIR>/keys/`, sorted
+pub(crate) fn accounts() -> Result<Vec<String>> {
+  migrate_legacy_key()?;
+
+  let mut accounts: Vec<String> = Vec::new();
+  for entry in read_dir(data_path("keys/")?)? {
+    let path: PathBuf = entry?.path();
+    if path.extension().is_some_and(|extension| extension == "pem") {
+      if let Some(account) = path.file_stem().and_then(|stem| stem.to_str()) {
+        accounts.push(account.to_string());
+      }
+    }
+  }
+  accounts.sort();
+  Ok(accounts)
+}
+
+
+/// Account names become file names
+pub(crate) fn check_account_name(account: &str) -> Result<()> {
+  ensure!(
+    !account.is_empty() && account.chars().all(|c: char| c.is_alphanumeric() || c == '-' || c == '_'),
+    "The account name may only contain letters, digits, `-` and `_`",
+  );
+  Ok(())
+}
+
+
+/// The encrypted key of the selected account
+pub(crate) fn key_path() -> Result<PathBuf> {
+  account_path(ACCOUNT.get().context("No account was selected")?)
+}
+
+
+pub(crate) fn account_path(account: &str) -> Result<PathBuf> {
+  Ok(data_path("keys/")?.join(format!("{account}.pem")))
+}
+
+
+/// Writes the key of a new account, an existing key is never overwritten
+pub(crate) fn save_new_key(key: &PrivateKey, password: &str) -> Result<()> {
+  write_new_key(&key_path()?, &key.encrypt(&mut OsRng, password)?)
+}
+
+
+/// Stores an already encrypted key as a new account
+pub(crate) fn import_key(account: &str, key: &PrivateKey) -> Result<()> {
+  check_account_name(account)?;
+  ensure!(key.is_encrypted(), "Only encrypted keys are imported");
+  write_new_key(&account_path(account)?, key)
+}
+
+
+/// Replaces the key file of the selected account with `key` encrypted by `password`.
+/// The old file stays in `<ACCOUNT>.pem.bak` until the new one is written and checked.
+pub(crate) fn replace_key(key: &PrivateKey, password: &str) -> Result<()> {
+  let path: PathBuf = key_path()?;
+  let backup_path: PathBuf = path.with_extension("pem.bak");
+  let new_path: PathBuf = path.with_extension("pem.new");
+
+  copy(&path, &backup_path)?;
+  key.encrypt(&mut OsRng, password)?.write_openssh_file(&new_path, LineEnding::LF)?;
+  let written: PrivateKey = PrivateKey::read_openssh_file(&new_path)?.decrypt(password)?;
+  ensure!(written.public_key() == key.public_key(), "The written key differs from the given key");
+
+  rename(&new_path, &path)?;
+  remove_file(&backup_path)?;
+  Ok(())
+}
+
+
+fn write_new_key(path: &Path, encrypted_key: &PrivateKey) -> Result<()> {
+  let new_path: PathBuf = path.with_extension("pem.new");
+  encrypted_key.write_openssh_file(&new_path, LineEnding::LF)?;
+
+  // Linking fails when the target exists, unlike renaming
+  let result: std::io::Result<()> = hard_link(&new_path, path);
+  remove_file(&new_path)?;
+  match result {
+    Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+      let account: &str = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+      bail!("The account {account} already exists")
+    },
+    result => Ok(result?),
+  }
+}
+
+
+/// The single key of older data directories becomes the default account
+fn migrate_legacy_key() -> Result<()> {
+  let legacy_path: PathBuf = data_dir()?.join("key.pem");
+  let path: PathBuf = account_path(DEFAULT_ACCOUNT)?;
+  if legacy_path.exists() && !path.exists() {
+    rename(&legacy_path, &path)?;
+    info!(account = DEFAULT_ACCOUNT, "Moved the key into the keystore");
+  }
+  Ok(())
+}
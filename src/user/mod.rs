@@ -15,12 +15,21 @@
 //!   along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 
-use anyhow::Result;
-use ssh_key::{PrivateKey, rand_core::OsRng, Algorithm, LineEnding};
+pub(crate) mod backup;
+pub(crate) mod keystore;
+
+
+use std::{fs::remove_file, path::PathBuf};
+
+use anyhow::{ensure, Result};
+use libp2p::PeerId;
+use ssh_key::{PrivateKey, PublicKey, rand_core::OsRng, Algorithm, LineEnding};
+use tracing::{debug, info, warn};
 
 use crate::{
-  blockchain::{Blockchain, data::user::UserData},
-  utils::data_path
+  blockchain::{Blockchain, block::Block, data::user::UserData, ledger::Ledger, user_record::UserQuery},
+  config::Config,
+  user::keystore::{key_path, replace_key, save_new_key},
 };
 
 
@@ -69,12 +78,30 @@ impl User {
     last_name: LN,
     user_name: UN,
     password: PW,
+    config: &Config,
   ) -> Result<Self> {
     let mut key: PrivateKey = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
     key.set_comment(user_name.clone());
-    key.encrypt(&mut OsRng, password.into())?.write_openssh_file(&data_path("")?.join("key.pem"), LineEnding::LF)?;
+    // Written first, so a taken account name fails before mining
+    save_new_key(&key, &password.into())?;
+
+    match Self::register(first_name, last_name, user_name, key.clone(), config) {
+      Ok(user) => Ok(user),
+      Err(error) => {
+        // A key without a stored registration can't log in, removing it frees the account name
+        if !Ledger::load().is_ok_and(|ledger: Ledger| ledger.is_known(key.public_key())) {
+          if let Err(error) = remove_file(key_path()?) {
+            warn!(%error, "Failed to remove the key of the unregistered account");
+          }
+        }
+        Err(error)
+      },
+    }
+  }
 
-    let blockchain: Blockchain = Blockchain::from_key(&key)?;
+
+  fn register<FN: Into<String>, LN: Into<String>, UN: Into<String>>(first_name: FN, last_name: LN, user_name: UN, key: PrivateKey, config: &Config) -> Result<Self> {
+    let blockchain: Blockchain = Blockchain::from_key(&key, config)?;
     
     let user_data: UserData = UserData::create_new(
       first_name,
@@ -85,20 +112,99 @@ impl User {
 
     let user: Self = Self::from_user_data(user_data, key, blockchain);
     user.blockchain.add_user(&user)?;
+    info!(user_name = %user.user_name, "Created a new identity");
     
     Ok(user)
   }
 
 
-  pub(crate) fn from_password(password: String) -> Result<Self> {
-    let key: PrivateKey = PrivateKey::read_openssh_file(&data_path("")?.join("key.pem"))?;
-    let key: PrivateKey = key.decrypt(password)?;
-    let blockchain: Blockchain = Blockchain::from_key(&key)?;
-    let user_data: UserData = blockchain.get_user(key.public_key())?;
+  /// `None` when the password is wrong
+  pub(crate) fn decrypt_key(password: &str) -> Result<Option<PrivateKey>> {
+    let key: PrivateKey = PrivateKey::read_openssh_file(&key_path()?)?;
+    match key.decrypt(password) {
+      Ok(key) => Ok(Some(key)),
+      Err(ssh_key::Error::Crypto) => Ok(None),
+      Err(error) => Err(error.into()),
+    }
+  }
+
+
+  pub(crate) fn from_key(key: PrivateKey, config: &Config) -> Result<Self> {
+    ensure!(!Ledger::load()?.is_retired(key.public_key()), "The key was replaced by a key rotation");
+    let blockchain: Blockchain = Blockchain::from_key(&key, config)?;
+    let user_data: UserData = blockchain.find_user(&UserQuery::PublicKey(key.public_key().clone()))?;
+    debug!(user_name = %user_data.get_user_name(), "Logged in");
     Ok(Self::from_user_data(user_data, key, blockchain))
   }
 
 
+  /// Encrypts the key of the logged in user with a new password
+  pub(crate) fn change_password(&self, password: &str) -> Result<()> {
+    replace_key(&self.key, password)?;
+    info!(user_name = %self.user_name, "Changed the password");
+    Ok(())
+  }
+
+
+  /// Moves the identity to a new key encrypted with the same password, the old key is retired in the chain
+  pub(crate) fn rotate_key(&mut self, password: &str) -> Result<Block> {
+    let mut new_key: PrivateKey = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+    new_key.set_comment(self.user_name.clone());
+    // Written before mining, the new key must survive a failure between mining and replacing the key file
+    let next_path: PathBuf = key_path()?.with_extension("pem.next");
+    new_key.encrypt(&mut OsRng, password)?.write_openssh_file(&next_path, LineEnding::LF)?;
+
+    let block: Block = match self.blockchain.rotate_key(self.get_key(), new_key.public_key()) {
+      Ok(block) => block,
+      Err(error) => {
+        // A rotation that isn't stored leaves the old key in use, its next key would only confuse the next attempt
+        if !Ledger::load().is_ok_and(|ledger: Ledger| ledger.is_retired(self.key.public_key())) {
+          if let Err(error) = remove_file(&next_path) {
+            warn!(%error, "Failed to remove the key of the failed rotation");
+          }
+        }
+        return Err(error);
+      },
+    };
+    replace_key(&new_key, password)?;
+    remove_file(&next_path)?;
+    self.key = new_key;
+    info!(user_name = %self.user_name, "Replaced the local key");
+    Ok(block)
+  }
+
+
+  pub(crate) fn find_user(&self, query: &UserQuery) -> Result<UserData> {
+    self.blockchain.find_user(query)
+  }
+
+
+  pub(crate) fn transfer(&self, to: &PublicKey, amount: f64) -> Result<Block> {
+    self.blockchain.transfer(self.get_key(), to, amount)
+  }
+
+
+  /// The balance of the user in the local chain
+  pub(crate) fn get_balance(&self) -> Result<f64> {
+    Ok(Ledger::load()?.get_balance(self.key.public_key()))
+  }
+
+
+  pub(crate) fn list_peers(&self) -> Result<Vec<PeerId>> {
+    self.blockchain.list_peers()
+  }
+
+
+  pub(crate) fn wait_for_peers(&self) -> Result<()> {
+    self.blockchain.wait_for_peers()
+  }
+
+
+  pub(crate) fn flush(&self) -> Result<bool> {
+    self.blockchain.flush()
+  }
+
+
   pub(crate) fn get_key(&self) -> PrivateKey {
     self.key.clone()
   }
@@ -124,5 +230,3 @@ impl User {
   }
 }
 
-
-
@@ -18,16 +18,47 @@
 use std::{
   path::{Path, PathBuf},
   fs::create_dir_all,
+  sync::OnceLock,
 };
 
 use anyhow::{Result, Context};
 use homedir::my_home;
 
 
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+
+pub(crate) fn set_data_dir<P: Into<PathBuf>>(path: P) -> Result<()> {
+  DATA_DIR.set(path.into()).ok().context("The data directory has already been set")?;
+  Ok(())
+}
+
+
+pub(crate) fn data_dir() -> Result<PathBuf> {
+  match DATA_DIR.get() {
+    Some(path) => Ok(path.clone()),
+    None => Ok(my_home()?.context("The user's home folder was not found")?.join(".system/")),
+  }
+}
+
+
 pub(crate) fn data_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
-  let path: PathBuf = my_home()?.context("The user's home folder was not found")?.join(".system/").join(path);
+  let path: PathBuf = data_dir()?.join(path);
   if !path.exists() {
     create_dir_all(&path)?;
   }
   Ok(path)
 }
+
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte: &u8| format!("{byte:02x}")).collect()
+}
+
+
+pub(crate) fn from_hex(hex: &str) -> Result<Vec<u8>> {
+  (0..hex.len()).step_by(2).map(|i: usize| {
+    let byte: &str = hex.get(i..i + 2).context("Invalid hexadecimal string")?;
+    Ok(u8::from_str_radix(byte, 16)?)
+  }).collect()
+}